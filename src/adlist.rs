@@ -58,7 +58,7 @@ mod tests {
 
     #[test]
     pub fn test_adlist() {
-        let mut stack = Stack::new()
+        let stack = Stack::new()
             .push(3)
             .push(2)
             .push(1);
@@ -1,43 +1,293 @@
-use std::net::{IpAddr, TcpStream, ToSocketAddrs};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::os::unix::net::UnixStream;
 use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use socket2::{SockRef, TcpKeepalive};
+use crate::error::RedisError;
 
-pub fn resolve_host(host: &str) -> Result<String, String> {
+/// 与服务端之间的双向字节流：TCP 连接或 Unix 域套接字
+#[derive(Debug)]
+pub enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Stream {
+    /// 复制句柄，两个句柄指向同一个套接字
+    pub fn try_clone(&self) -> io::Result<Stream> {
+        match self {
+            Stream::Tcp(s) => s.try_clone().map(Stream::Tcp),
+            Stream::Unix(s) => s.try_clone().map(Stream::Unix),
+        }
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.shutdown(how),
+            Stream::Unix(s) => s.shutdown(how),
+        }
+    }
+
+    pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        match self {
+            Stream::Tcp(s) => s.read_timeout(),
+            Stream::Unix(s) => s.read_timeout(),
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.set_read_timeout(timeout),
+            Stream::Unix(s) => s.set_read_timeout(timeout),
+        }
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.set_write_timeout(timeout),
+            Stream::Unix(s) => s.set_write_timeout(timeout),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.read(buf),
+            Stream::Unix(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.write(buf),
+            Stream::Unix(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.flush(),
+            Stream::Unix(s) => s.flush(),
+        }
+    }
+}
+
+impl From<TcpStream> for Stream {
+    fn from(stream: TcpStream) -> Self {
+        Stream::Tcp(stream)
+    }
+}
+
+impl From<UnixStream> for Stream {
+    fn from(stream: UnixStream) -> Self {
+        Stream::Unix(stream)
+    }
+}
+
+/// 解析主机名时优先使用的地址族
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AddressFamily {
+    /// 按解析结果的顺序
+    #[default]
+    Any,
+    Ipv4,
+    Ipv6,
+}
+
+/// 解析主机名，返回全部地址，`family` 指定的地址族排在前面。IP 字面量（含不带方括号的 IPv6）直接返回
+pub fn resolve_host(host: &str, family: AddressFamily) -> Result<Vec<IpAddr>, RedisError> {
     if let Ok(ip) = IpAddr::from_str(host) {
-        return Ok(ip.to_string());
+        return Ok(vec![ip]);
     }
 
-    let target = format!("{}:1", host);
-    match target.to_socket_addrs() {
-        Ok(mut addrs) => {
-            if let Some(addr) = addrs.next() {
-                Ok(addr.ip().to_string())
-            } else {
-                Err(format!("cannot resolve host: {}", host))
+    let mut ips: Vec<IpAddr> = Vec::new();
+    for addr in (host, 0).to_socket_addrs().map_err(|_| RedisError::Resolve(host.to_string()))? {
+        if !ips.contains(&addr.ip()) {
+            ips.push(addr.ip());
+        }
+    }
+    if ips.is_empty() {
+        return Err(RedisError::Resolve(host.to_string()));
+    }
+    sort_by_family(&mut ips, family);
+    Ok(ips)
+}
+
+/// 稳定排序，同一地址族内保持解析结果的顺序
+fn sort_by_family(ips: &mut [IpAddr], family: AddressFamily) {
+    match family {
+        AddressFamily::Any => {}
+        AddressFamily::Ipv4 => ips.sort_by_key(|ip| !ip.is_ipv4()),
+        AddressFamily::Ipv6 => ips.sort_by_key(|ip| !ip.is_ipv6()),
+    }
+}
+
+/// 格式化 `host:port`，IPv6 地址加方括号，如 `[::1]:6379`
+pub fn format_addr(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// 连接 `addr:port`，依次尝试解析出的每个地址直到成功。
+///
+/// `timeout` 是所有尝试共用的总超时，`None` 表示每个地址都一直等待。全部失败时返回最后一个错误
+pub fn tcp_generic_connect(
+    addr: &str,
+    port: u16,
+    non_blocking: bool,
+    timeout: Option<Duration>,
+    family: AddressFamily,
+) -> Result<TcpStream, RedisError> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut last_err = RedisError::Resolve(addr.to_string());
+    for ip in resolve_host(addr, family)? {
+        let target = SocketAddr::new(ip, port);
+        let result = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()).filter(|d| !d.is_zero()) {
+                Some(remaining) => TcpStream::connect_timeout(&target, remaining),
+                None => {
+                    last_err = io::Error::from(io::ErrorKind::TimedOut).into();
+                    break;
+                }
+            },
+            None => TcpStream::connect(target),
+        };
+        match result {
+            Ok(stream) => {
+                if non_blocking {
+                    stream.set_nonblocking(true)?;
+                }
+                return Ok(stream);
             }
+            Err(err) => last_err = err.into(),
         }
-        Err(_) => Err(format!("cannot resolve host: {}", host)),
     }
+    Err(last_err)
 }
 
-pub fn tcp_generic_connect(addr: &str, port: u16, non_blocking: bool) -> Result<TcpStream, String> {
-    let addrs = format!("{}:{}", addr, port).to_socket_addrs().map_err(|e| e.to_string())?;
-    let stream = TcpStream::connect_timeout(&addrs.into_iter().next().ok_or("No valid addr")?, Duration::from_secs(1))
-        .map_err(|e| e.to_string())?;
+pub fn tcp_connect(addr: &str, port: u16, timeout: Option<Duration>, family: AddressFamily) -> Result<TcpStream, RedisError> {
+    tcp_generic_connect(addr, port, false, timeout, family)
+}
+
+pub fn tcp_non_block_connect(
+    addr: &str,
+    port: u16,
+    timeout: Option<Duration>,
+    family: AddressFamily,
+) -> Result<TcpStream, RedisError> {
+    tcp_generic_connect(addr, port, true, timeout, family)
+}
+
+/// 连接 Unix 域套接字 `path`
+pub fn unix_generic_connect(path: &str, non_blocking: bool) -> Result<UnixStream, RedisError> {
+    let stream = UnixStream::connect(path)?;
 
     if non_blocking {
-        stream
-            .set_nonblocking(true)
-            .map_err(|e| format!("set non-blocking error: {}", e))?;
+        stream.set_nonblocking(true)?;
     }
 
     Ok(stream)
 }
 
-pub fn tcp_connect(addr: &str, port: u16) -> Result<TcpStream, String> {
-    tcp_generic_connect(addr, port, false)
+pub fn unix_connect(path: &str) -> Result<UnixStream, RedisError> {
+    unix_generic_connect(path, false)
 }
 
-pub fn tcp_non_block_connect(addr: &str, port: u16) -> Result<TcpStream, String> {
-    tcp_generic_connect(addr, port, true)
+pub fn unix_non_block_connect(path: &str) -> Result<UnixStream, RedisError> {
+    unix_generic_connect(path, true)
+}
+
+/// 开启或关闭 TCP_NODELAY。关闭 Nagle 算法后小包立即发出，流水线请求不再互相等待
+pub fn tcp_no_delay(stream: &TcpStream, on: bool) -> Result<(), RedisError> {
+    Ok(SockRef::from(stream).set_tcp_nodelay(on)?)
+}
+
+/// 开启 SO_KEEPALIVE，仿照 anet.c 的 anetKeepAlive：空闲 `interval` 后开始探测，
+/// 每 `interval / 3` 探测一次，连续 3 次无响应即认为连接已断开。用于发现被 NAT 静默丢弃的连接
+pub fn tcp_keep_alive(stream: &TcpStream, interval: Duration) -> Result<(), RedisError> {
+    let probe = (interval / 3).max(Duration::from_secs(1));
+    let keepalive = TcpKeepalive::new().with_time(interval).with_interval(probe).with_retries(3);
+    Ok(SockRef::from(stream).set_tcp_keepalive(&keepalive)?)
+}
+
+/// 设置发送缓冲区大小（SO_SNDBUF），内核可能会调整实际值
+pub fn set_send_buffer(stream: &TcpStream, size: usize) -> Result<(), RedisError> {
+    Ok(SockRef::from(stream).set_send_buffer_size(size)?)
+}
+
+/// 设置接收缓冲区大小（SO_RCVBUF），内核可能会调整实际值
+pub fn set_recv_buffer(stream: &TcpStream, size: usize) -> Result<(), RedisError> {
+    Ok(SockRef::from(stream).set_recv_buffer_size(size)?)
+}
+
+/// 设置读超时，`None` 表示一直等待。超时后读操作返回 `RedisError::Timeout`
+pub fn recv_timeout(stream: &Stream, timeout: Option<Duration>) -> Result<(), RedisError> {
+    Ok(stream.set_read_timeout(timeout)?)
+}
+
+/// 设置写超时，`None` 表示一直等待
+pub fn send_timeout(stream: &Stream, timeout: Option<Duration>) -> Result<(), RedisError> {
+    Ok(stream.set_write_timeout(timeout)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::anet::{
+        format_addr, resolve_host, set_recv_buffer, set_send_buffer, sort_by_family, tcp_connect, tcp_keep_alive,
+        tcp_no_delay, AddressFamily,
+    };
+    use socket2::SockRef;
+    use std::net::{IpAddr, TcpListener};
+    use std::time::Duration;
+
+    #[test]
+    fn test_addresses() {
+        assert_eq!(format_addr("::1", 6379), "[::1]:6379");
+        assert_eq!(format_addr("127.0.0.1", 6379), "127.0.0.1:6379");
+        assert_eq!(resolve_host("::1", AddressFamily::Ipv4).unwrap(), ["::1".parse::<IpAddr>().unwrap()]);
+
+        let v4: IpAddr = "127.0.0.1".parse().unwrap();
+        let v6: IpAddr = "::1".parse().unwrap();
+        let mut ips = [v4, v6];
+        sort_by_family(&mut ips, AddressFamily::Ipv6);
+        assert_eq!(ips, [v6, v4]);
+        sort_by_family(&mut ips, AddressFamily::Ipv4);
+        assert_eq!(ips, [v4, v6]);
+    }
+
+    #[test]
+    fn test_connect_falls_back_to_other_addresses() {
+        // 只监听 IPv4；localhost 若先解析出 ::1，连接被拒后应继续尝试 127.0.0.1
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let stream = tcp_connect("localhost", port, Some(Duration::from_secs(1)), AddressFamily::Ipv6).unwrap();
+        assert!(stream.peer_addr().unwrap().is_ipv4());
+    }
+
+    #[test]
+    fn test_socket_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = tcp_connect("127.0.0.1", listener.local_addr().unwrap().port(), None, AddressFamily::Any).unwrap();
+
+        tcp_no_delay(&stream, true).unwrap();
+        assert!(stream.nodelay().unwrap());
+        tcp_keep_alive(&stream, Duration::from_secs(15)).unwrap();
+        let sock = SockRef::from(&stream);
+        assert!(sock.keepalive().unwrap());
+        assert_eq!(sock.tcp_keepalive_time().unwrap(), Duration::from_secs(15));
+        assert_eq!(sock.tcp_keepalive_interval().unwrap(), Duration::from_secs(5));
+
+        // Linux 会把设置的值翻倍，只检查不小于设置值
+        set_send_buffer(&stream, 64 * 1024).unwrap();
+        set_recv_buffer(&stream, 64 * 1024).unwrap();
+        assert!(sock.send_buffer_size().unwrap() >= 64 * 1024);
+        assert!(sock.recv_buffer_size().unwrap() >= 64 * 1024);
+    }
 }
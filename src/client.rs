@@ -0,0 +1,241 @@
+use crate::error::RedisError;
+use crate::redis_cli::{connect, init, redis_commands, Config, Connection, Protocol, PubSubMessage, RedisCommand, RedisValue};
+
+/// 类型化的客户端，每个命令对应一个方法（由命令表生成）。
+///
+/// 服务端的错误回复以 `RedisError::Server` 返回，其余回复原样返回。
+///
+/// ```no_run
+/// use r_cli::{Client, Config, RedisValue};
+///
+/// let mut client = Client::connect(&Config::new())?;
+/// client.set("greeting", "hello")?;
+/// assert_eq!(client.get("greeting")?, RedisValue::Bulk(b"hello".to_vec()));
+/// # Ok::<(), r_cli::RedisError>(())
+/// ```
+pub struct Client {
+    conn: Connection,
+    config: Config,
+}
+
+impl Client {
+    /// 按 `config` 连接服务端，并执行 AUTH 与 SELECT
+    pub fn connect(config: &Config) -> Result<Self, RedisError> {
+        let mut client = Client { conn: connect(config)?, config: config.clone() };
+        init(&client.config, &mut client.conn)?;
+        Ok(client)
+    }
+
+    /// 发送任意命令并返回回复，命令名与参数个数按命令表校验
+    pub fn command<T: AsRef<[u8]>>(&mut self, args: &[T]) -> Result<RedisValue, RedisError> {
+        let rc = RedisCommand::build(args)?;
+        let reply = self.conn.request(&rc, self.config.protocol)?;
+        self.conn.track_transaction(&rc, &reply);
+        match reply {
+            RedisValue::Error(err) => Err(RedisError::Server(err)),
+            reply => Ok(reply),
+        }
+    }
+
+    /// 取回底层连接
+    pub fn into_connection(self) -> Connection {
+        self.conn
+    }
+
+    /// 转为订阅连接。订阅后连接只能收发发布订阅相关的命令
+    pub fn into_subscriber(self) -> Subscriber {
+        // 消息随时可能到达，等待消息不受读超时限制
+        let _ = self.conn.set_read_timeout(None);
+        Subscriber { conn: self.conn, protocol: self.config.protocol }
+    }
+}
+
+/// 订阅连接。`subscribe` 等方法只发送命令，确认与消息都通过迭代器按到达顺序取得。
+///
+/// ```no_run
+/// use r_cli::{Client, Config, PubSubMessage};
+///
+/// let mut subscriber = Client::connect(&Config::new())?.into_subscriber();
+/// subscriber.subscribe(&["news"])?;
+/// for message in subscriber {
+///     if let PubSubMessage::Message { channel, payload } = message? {
+///         println!("{}: {}", String::from_utf8_lossy(&channel), String::from_utf8_lossy(&payload));
+///     }
+/// }
+/// # Ok::<(), r_cli::RedisError>(())
+/// ```
+pub struct Subscriber {
+    conn: Connection,
+    protocol: Protocol,
+}
+
+impl Subscriber {
+    /// 发送订阅相关的命令，不等待确认
+    pub fn send_command<T: AsRef<[u8]>>(&mut self, args: &[T]) -> Result<(), RedisError> {
+        let rc = RedisCommand::build(args)?;
+        self.conn.send(&rc.encode(self.protocol))
+    }
+
+    /// 阻塞读取下一条推送消息
+    pub fn next_message(&mut self) -> Result<PubSubMessage, RedisError> {
+        let reply = self.conn.read_reply()?;
+        PubSubMessage::from_reply(&reply).ok_or_else(|| match reply {
+            RedisValue::Error(err) => RedisError::Server(err),
+            other => RedisError::Protocol(format!("unexpected reply in pub/sub mode: {:?}", other)),
+        })
+    }
+}
+
+impl Iterator for Subscriber {
+    type Item = Result<PubSubMessage, RedisError>;
+
+    /// 连接被正常关闭时结束，连接重置等其它错误照常返回
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_message() {
+            Err(err) if err.is_eof() => None,
+            other => Some(other),
+        }
+    }
+}
+
+macro_rules! client_methods {
+    (
+        commands { $($name:ident => $method:ident $params:tt, $arity:expr, $flags:ident;)* }
+        // MONITOR 之后连接只接收推送，之后的命令都会读到推送的内容，不提供方法
+        monitor { $($mname:ident => $mmethod:ident $mparams:tt, $marity:expr, $mflags:ident;)* }
+        pubsub { $($pname:ident => $pmethod:ident $pparams:tt, $parity:expr, $pflags:ident;)* }
+    ) => {
+        impl Client {
+            $(client_methods!(@method $name => $method $params -> RedisValue, command);)*
+        }
+
+        impl Subscriber {
+            $(client_methods!(@method $pname => $pmethod $pparams -> (), send_command);)*
+        }
+    };
+    (@method $name:ident => $method:ident($($arg:ident),* $(; $rest:ident $(, $tail:ident)*)?) -> $ret:ty, $send:ident) => {
+        #[doc = concat!("发送 `", stringify!($name), "` 命令")]
+        pub fn $method(
+            &mut self,
+            $($arg: impl AsRef<[u8]>,)*
+            $($rest: &[impl AsRef<[u8]>], $($tail: impl AsRef<[u8]>,)*)?
+        ) -> Result<$ret, RedisError> {
+            #[allow(unused_mut)]
+            let mut args: Vec<Vec<u8>> = vec![stringify!($name).as_bytes().to_vec()];
+            $(args.push($arg.as_ref().to_vec());)*
+            $(
+                args.extend($rest.iter().map(|a| a.as_ref().to_vec()));
+                $(args.push($tail.as_ref().to_vec());)*
+            )?
+            self.$send(&args)
+        }
+    };
+}
+
+redis_commands!(client_methods);
+
+#[cfg(test)]
+mod tests {
+    use crate::client::Client;
+    use crate::redis_cli::{Config, Protocol, PubSubMessage, RedisValue};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    /// 按顺序校验收到的请求字节，并回复预先准备好的内容
+    fn spawn_server(exchanges: Vec<(&'static [u8], &'static [u8])>) -> Config {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut config = Config::new();
+        config.hostport = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut sock, _) = listener.accept().unwrap();
+            for (request, reply) in exchanges {
+                let mut buf = vec![0u8; request.len()];
+                sock.read_exact(&mut buf).unwrap();
+                assert_eq!(buf, request, "unexpected request {:?}", String::from_utf8_lossy(&buf));
+                sock.write_all(reply).unwrap();
+            }
+        });
+        config
+    }
+
+    #[test]
+    fn test_generated_methods() {
+        let config = spawn_server(vec![
+            (b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n", b"+OK\r\n"),
+            (b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n", b"$1\r\nv\r\n"),
+            (b"*4\r\n$5\r\nBLPOP\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\n0\r\n", b"*-1\r\n"),
+            (b"*5\r\n$4\r\nMSET\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n$1\r\n2\r\n", b"+OK\r\n"),
+            (b"*2\r\n$4\r\nINCR\r\n$1\r\nk\r\n", b"-ERR value is not an integer\r\n"),
+        ]);
+
+        let mut client = Client::connect(&config).unwrap();
+        assert_eq!(client.set("k", "v").unwrap(), RedisValue::Status("OK".to_string()));
+        assert_eq!(client.get("k").unwrap(), RedisValue::Bulk(b"v".to_vec()));
+        assert_eq!(client.blpop(&["a", "b"], "0").unwrap(), RedisValue::Nil);
+        assert_eq!(client.mset(&["a", "1", "b", "2"]).unwrap(), RedisValue::Status("OK".to_string()));
+        assert!(client.incr("k").unwrap_err().is_server());
+        // 参数个数在本地校验，不会发到服务端
+        assert!(client.del(&[] as &[&str]).unwrap_err().is_command());
+    }
+
+    #[test]
+    fn test_legacy_protocol() {
+        let mut config = spawn_server(vec![
+            (b"SET k 3\r\nbar\r\n", b"+OK\r\n"),
+            (b"GET k\r\n", b"$3\r\nbar\r\n"),
+            (b"*4\r\n$4\r\nHSET\r\n$1\r\nh\r\n$1\r\nf\r\n$1\r\nv\r\n", b":1\r\n"),
+        ]);
+        config.protocol = Protocol::Legacy;
+
+        let mut client = Client::connect(&config).unwrap();
+        assert_eq!(client.set("k", "bar").unwrap(), RedisValue::Status("OK".to_string()));
+        assert_eq!(client.get("k").unwrap(), RedisValue::Bulk(b"bar".to_vec()));
+        assert_eq!(client.hset("h", "f", "v").unwrap(), RedisValue::Integer(1));
+    }
+
+    #[test]
+    fn test_subscriber() {
+        let config = spawn_server(vec![(
+            b"*3\r\n$9\r\nSUBSCRIBE\r\n$4\r\nnews\r\n$5\r\nsport\r\n",
+            b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n\
+              *3\r\n$9\r\nsubscribe\r\n$5\r\nsport\r\n:2\r\n\
+              *3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n",
+        )]);
+
+        let mut subscriber = Client::connect(&config).unwrap().into_subscriber();
+        subscriber.subscribe(&["news", "sport"]).unwrap();
+        // 服务端写完后关闭连接，迭代随之结束
+        let messages: Vec<PubSubMessage> = subscriber.map(|m| m.unwrap()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                PubSubMessage::Subscription { kind: "subscribe".to_string(), channel: Some(b"news".to_vec()), count: 1 },
+                PubSubMessage::Subscription { kind: "subscribe".to_string(), channel: Some(b"sport".to_vec()), count: 2 },
+                PubSubMessage::Message { channel: b"news".to_vec(), payload: b"hello".to_vec() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_subscriber_reports_reset() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut config = Config::new();
+        config.hostport = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut sock, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 64];
+            let _ = sock.read(&mut buf).unwrap();
+            sock.write_all(b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n").unwrap();
+            // SO_LINGER 为 0 时关闭会发送 RST 而不是 FIN
+            socket2::SockRef::from(&sock).set_linger(Some(Duration::ZERO)).unwrap();
+        });
+
+        let mut subscriber = Client::connect(&config).unwrap().into_subscriber();
+        subscriber.subscribe(&["news"]).unwrap();
+        assert!(matches!(subscriber.next(), Some(Ok(PubSubMessage::Subscription { count: 1, .. }))));
+        let err = subscriber.next().expect("reset must not end the stream silently").unwrap_err();
+        assert!(err.is_io() && !err.is_eof());
+    }
+}
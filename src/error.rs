@@ -0,0 +1,101 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// anet 与 redis_cli 共用的错误类型
+#[derive(Debug)]
+pub enum RedisError {
+    /// 主机名无法解析
+    Resolve(String),
+    /// 连接、读或写超时
+    Timeout(io::Error),
+    /// 其它 I/O 错误，包括连接被对端关闭
+    Io(io::Error),
+    /// 服务端的回复不符合协议
+    Protocol(String),
+    /// 服务端返回的错误回复（`-ERR ...`）
+    Server(String),
+    /// 客户端本地校验失败：未知命令、参数个数错误、引号不匹配
+    Command(String),
+}
+
+impl RedisError {
+    pub fn is_resolve(&self) -> bool {
+        matches!(self, RedisError::Resolve(_))
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, RedisError::Timeout(_))
+    }
+
+    pub fn is_io(&self) -> bool {
+        matches!(self, RedisError::Io(_))
+    }
+
+    /// 连接在两条回复之间被对端正常关闭
+    pub fn is_eof(&self) -> bool {
+        matches!(self, RedisError::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof)
+    }
+
+    pub fn is_protocol(&self) -> bool {
+        matches!(self, RedisError::Protocol(_))
+    }
+
+    pub fn is_server(&self) -> bool {
+        matches!(self, RedisError::Server(_))
+    }
+
+    pub fn is_command(&self) -> bool {
+        matches!(self, RedisError::Command(_))
+    }
+}
+
+impl fmt::Display for RedisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RedisError::Resolve(host) => write!(f, "cannot resolve host: {}", host),
+            RedisError::Timeout(e) => write!(f, "timed out: {}", e),
+            RedisError::Io(e) => write!(f, "{}", e),
+            RedisError::Protocol(msg) => write!(f, "protocol error: {}", msg),
+            RedisError::Server(msg) => write!(f, "{}", msg),
+            RedisError::Command(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Error for RedisError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RedisError::Timeout(e) | RedisError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RedisError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            // 设置了读写超时的阻塞 socket 超时后返回 WouldBlock（Unix）或 TimedOut（Windows）
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => RedisError::Timeout(e),
+            _ => RedisError::Io(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::RedisError;
+    use std::error::Error;
+    use std::io;
+
+    #[test]
+    fn test_io_errors_are_classified() {
+        let timeout = RedisError::from(io::Error::from(io::ErrorKind::WouldBlock));
+        assert!(timeout.is_timeout());
+        let refused = RedisError::from(io::Error::from(io::ErrorKind::ConnectionRefused));
+        assert!(refused.is_io() && !refused.is_timeout());
+        assert!(refused.source().is_some());
+        assert!(RedisError::Server("ERR x".to_string()).is_server());
+        assert_eq!(RedisError::Protocol("bad".to_string()).to_string(), "protocol error: bad");
+    }
+}
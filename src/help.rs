@@ -0,0 +1,249 @@
+//! 命令文档，仿照 redis-cli 的 help.h。
+//!
+//! 与 `CMD_TABLE` 一一对应：用法由命令表的参数名生成，这里只记录说明、引入版本与分组。
+
+use crate::redis_cli::CMD_TABLE;
+
+/// 命令分组，`help @<group>` 按组列出命令
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Group {
+    Generic,
+    String,
+    List,
+    Set,
+    SortedSet,
+    Hash,
+    PubSub,
+    Transactions,
+    Connection,
+    Server,
+}
+
+impl Group {
+    pub const ALL: [Group; 10] = [
+        Group::Generic,
+        Group::String,
+        Group::List,
+        Group::Set,
+        Group::SortedSet,
+        Group::Hash,
+        Group::PubSub,
+        Group::Transactions,
+        Group::Connection,
+        Group::Server,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Group::Generic => "generic",
+            Group::String => "string",
+            Group::List => "list",
+            Group::Set => "set",
+            Group::SortedSet => "sorted_set",
+            Group::Hash => "hash",
+            Group::PubSub => "pubsub",
+            Group::Transactions => "transactions",
+            Group::Connection => "connection",
+            Group::Server => "server",
+        }
+    }
+
+    /// 按名字查找分组，不区分大小写
+    pub fn from_name(name: &str) -> Option<Group> {
+        Group::ALL.into_iter().find(|g| g.name().eq_ignore_ascii_case(name))
+    }
+}
+
+pub struct CommandDoc {
+    pub name: &'static str,
+    pub summary: &'static str,
+    /// 引入该命令的 Redis 版本
+    pub since: &'static str,
+    pub group: Group,
+}
+
+macro_rules! docs {
+    ($($name:ident, $group:ident, $since:literal, $summary:literal;)*) => {
+        pub static COMMAND_DOCS: &[CommandDoc] = &[
+            $(CommandDoc { name: stringify!($name), summary: $summary, since: $since, group: Group::$group },)*
+        ];
+    };
+}
+
+docs! {
+    AUTH, Connection, "1.0.0", "Authenticate to the server";
+    GET, String, "1.0.0", "Get the value of a key";
+    SET, String, "1.0.0", "Set the string value of a key";
+    SETNX, String, "1.0.0", "Set the value of a key, only if the key does not exist";
+    APPEND, String, "2.0.0", "Append a value to a key";
+    SUBSTR, String, "1.0.0", "Get a substring of the string stored at a key";
+    DEL, Generic, "1.0.0", "Delete a key";
+    EXISTS, Generic, "1.0.0", "Determine if a key exists";
+    INCR, String, "1.0.0", "Increment the integer value of a key by one";
+    DECR, String, "1.0.0", "Decrement the integer value of a key by one";
+    RPUSH, List, "1.0.0", "Append a value to a list";
+    LPUSH, List, "1.0.0", "Prepend a value to a list";
+    RPOP, List, "1.0.0", "Remove and get the last element in a list";
+    LPOP, List, "1.0.0", "Remove and get the first element in a list";
+    BRPOP, List, "2.0.0", "Remove and get the last element in a list, or block until one is available";
+    BLPOP, List, "2.0.0", "Remove and get the first element in a list, or block until one is available";
+    LLEN, List, "1.0.0", "Get the length of a list";
+    LINDEX, List, "1.0.0", "Get an element from a list by its index";
+    LSET, List, "1.0.0", "Set the value of an element in a list by its index";
+    LRANGE, List, "1.0.0", "Get a range of elements from a list";
+    LTRIM, List, "1.0.0", "Trim a list to the specified range";
+    LREM, List, "1.0.0", "Remove elements from a list";
+    RPOPLPUSH, List, "1.2.0", "Remove the last element in a list, append it to another list and return it";
+    SADD, Set, "1.0.0", "Add a member to a set";
+    SREM, Set, "1.0.0", "Remove a member from a set";
+    SMOVE, Set, "1.0.0", "Move a member from one set to another";
+    SISMEMBER, Set, "1.0.0", "Determine if a given value is a member of a set";
+    SCARD, Set, "1.0.0", "Get the number of members in a set";
+    SPOP, Set, "1.0.0", "Remove and return a random member from a set";
+    SRANDMEMBER, Set, "1.0.0", "Get a random member from a set";
+    SINTER, Set, "1.0.0", "Intersect multiple sets";
+    SINTERSTORE, Set, "1.0.0", "Intersect multiple sets and store the resulting set in a key";
+    SUNION, Set, "1.0.0", "Add multiple sets";
+    SUNIONSTORE, Set, "1.0.0", "Add multiple sets and store the resulting set in a key";
+    SDIFF, Set, "1.0.0", "Subtract multiple sets";
+    SDIFFSTORE, Set, "1.0.0", "Subtract multiple sets and store the resulting set in a key";
+    SMEMBERS, Set, "1.0.0", "Get all the members in a set";
+    ZADD, SortedSet, "1.2.0", "Add a member to a sorted set, or update its score if it already exists";
+    ZINCRBY, SortedSet, "1.2.0", "Increment the score of a member in a sorted set";
+    ZREM, SortedSet, "1.2.0", "Remove a member from a sorted set";
+    ZREMRANGEBYSCORE, SortedSet, "1.2.0", "Remove all members in a sorted set within the given scores";
+    ZRANGE, SortedSet, "1.2.0", "Return a range of members in a sorted set, by index";
+    ZRANK, SortedSet, "2.0.0", "Determine the index of a member in a sorted set";
+    ZREVRANK, SortedSet, "2.0.0", "Determine the index of a member in a sorted set, with scores ordered from high to low";
+    ZRANGEBYSCORE, SortedSet, "1.0.5", "Return a range of members in a sorted set, by score";
+    ZCOUNT, SortedSet, "2.0.0", "Count the members in a sorted set with scores within the given values";
+    ZREVRANGE, SortedSet, "1.2.0", "Return a range of members in a sorted set, by index, with scores ordered from high to low";
+    ZCARD, SortedSet, "1.2.0", "Get the number of members in a sorted set";
+    ZSCORE, SortedSet, "1.2.0", "Get the score associated with the given member in a sorted set";
+    INCRBY, String, "1.0.0", "Increment the integer value of a key by the given number";
+    DECRBY, String, "1.0.0", "Decrement the integer value of a key by the given number";
+    GETSET, String, "1.0.0", "Set the string value of a key and return its old value";
+    RANDOMKEY, Generic, "1.0.0", "Return a random key from the keyspace";
+    SELECT, Connection, "1.0.0", "Change the selected database for the current connection";
+    MOVE, Generic, "1.0.0", "Move a key to another database";
+    RENAME, Generic, "1.0.0", "Rename a key";
+    RENAMENX, Generic, "1.0.0", "Rename a key, only if the new key does not exist";
+    KEYS, Generic, "1.0.0", "Find all keys matching the given pattern";
+    DBSIZE, Server, "1.0.0", "Return the number of keys in the selected database";
+    PING, Connection, "1.0.0", "Ping the server";
+    ECHO, Connection, "1.0.0", "Echo the given string";
+    SAVE, Server, "1.0.0", "Synchronously save the dataset to disk";
+    BGSAVE, Server, "1.0.0", "Asynchronously save the dataset to disk";
+    BGREWRITEAOF, Server, "1.0.0", "Asynchronously rewrite the append-only file";
+    SHUTDOWN, Server, "1.0.0", "Synchronously save the dataset to disk and then shut down the server";
+    LASTSAVE, Server, "1.0.0", "Get the UNIX time stamp of the last successful save to disk";
+    TYPE, Generic, "1.0.0", "Determine the type stored at key";
+    FLUSHDB, Server, "1.0.0", "Remove all keys from the current database";
+    FLUSHALL, Server, "1.0.0", "Remove all keys from all databases";
+    SORT, Generic, "1.0.0", "Sort the elements in a list, set or sorted set";
+    INFO, Server, "1.0.0", "Get information and statistics about the server";
+    MGET, String, "1.0.0", "Get the values of all the given keys";
+    EXPIRE, Generic, "1.0.0", "Set a key's time to live in seconds";
+    EXPIREAT, Generic, "1.2.0", "Set the expiration for a key as a UNIX timestamp";
+    TTL, Generic, "1.0.0", "Get the time to live for a key";
+    SLAVEOF, Server, "1.0.0", "Make the server a slave of another instance, or promote it as master";
+    DEBUG, Server, "1.0.0", "Debugging commands such as OBJECT, RELOAD and SEGFAULT";
+    MSET, String, "1.0.1", "Set multiple keys to multiple values";
+    MSETNX, String, "1.0.1", "Set multiple keys to multiple values, only if none of the keys exist";
+    MONITOR, Server, "1.0.0", "Listen for all requests received by the server in real time";
+    MULTI, Transactions, "1.2.0", "Mark the start of a transaction block";
+    EXEC, Transactions, "1.2.0", "Execute all commands issued after MULTI";
+    DISCARD, Transactions, "2.0.0", "Discard all commands issued after MULTI";
+    HSET, Hash, "2.0.0", "Set the string value of a hash field";
+    HGET, Hash, "2.0.0", "Get the value of a hash field";
+    HDEL, Hash, "2.0.0", "Delete a hash field";
+    HLEN, Hash, "2.0.0", "Get the number of fields in a hash";
+    HKEYS, Hash, "2.0.0", "Get all the fields in a hash";
+    HVALS, Hash, "2.0.0", "Get all the values in a hash";
+    HGETALL, Hash, "2.0.0", "Get all the fields and values in a hash";
+    HEXISTS, Hash, "2.0.0", "Determine if a hash field exists";
+    PUBLISH, PubSub, "2.0.0", "Post a message to a channel";
+    SUBSCRIBE, PubSub, "2.0.0", "Listen for messages published to the given channels";
+    UNSUBSCRIBE, PubSub, "2.0.0", "Stop listening for messages posted to the given channels";
+    PSUBSCRIBE, PubSub, "2.0.0", "Listen for messages published to channels matching the given patterns";
+    PUNSUBSCRIBE, PubSub, "2.0.0", "Stop listening for messages posted to channels matching the given patterns";
+}
+
+/// 按命令名查找文档，不区分大小写
+pub fn command_doc(name: &str) -> Option<&'static CommandDoc> {
+    COMMAND_DOCS.iter().find(|doc| doc.name.eq_ignore_ascii_case(name))
+}
+
+/// `help` 的输出。`topic` 为空时打印用法，`@group` 列出该组的全部命令，否则打印单个命令
+pub fn help_text(topic: Option<&str>) -> String {
+    let Some(topic) = topic else {
+        return format!(
+            "r-cli {}\n\
+             Type: \"help @<group>\" to get a list of commands in <group>\n      \
+             \"help <command>\" for help on <command>\n      \
+             \"help <tab>\" to get a list of possible help topics\n      \
+             \"quit\" to exit\n",
+            env!("CARGO_PKG_VERSION")
+        );
+    };
+
+    let docs: Vec<&CommandDoc> = match topic.strip_prefix('@') {
+        Some(group) => match Group::from_name(group) {
+            Some(group) => COMMAND_DOCS.iter().filter(|doc| doc.group == group).collect(),
+            None => return format!("No such group: {}\n", group),
+        },
+        None => match command_doc(topic) {
+            Some(doc) => vec![doc],
+            None => return format!("No help for '{}', type \"help\" for a list of help topics\n", topic),
+        },
+    };
+
+    let mut text = String::new();
+    for doc in docs {
+        text += "\n";
+        if let Some(cmd) = CMD_TABLE.iter().find(|c| c.name == doc.name) {
+            text += &format!("  {}\n", cmd.syntax());
+        }
+        text += &format!("  summary: {}\n", doc.summary);
+        text += &format!("  since: {}\n", doc.since);
+        text += &format!("  group: {}\n", doc.group.name());
+    }
+    text + "\n"
+}
+
+/// `help` 可用的主题：全部命令名与 `@group`
+pub fn help_topics() -> impl Iterator<Item = String> {
+    COMMAND_DOCS
+        .iter()
+        .map(|doc| doc.name.to_string())
+        .chain(Group::ALL.into_iter().map(|g| format!("@{}", g.name())))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::help::{command_doc, help_text, COMMAND_DOCS};
+    use crate::redis_cli::CMD_TABLE;
+
+    #[test]
+    fn test_every_command_is_documented() {
+        assert_eq!(COMMAND_DOCS.len(), CMD_TABLE.len());
+        for cmd in CMD_TABLE {
+            assert!(command_doc(cmd.name).is_some(), "{} has no help entry", cmd.name);
+        }
+    }
+
+    #[test]
+    fn test_help_text() {
+        assert_eq!(
+            help_text(Some("zrangebyscore")),
+            "\n  ZRANGEBYSCORE key min max [option ...]\n  summary: Return a range of members in a sorted set, by score\n  \
+             since: 1.0.5\n  group: sorted_set\n\n"
+        );
+        let transactions = help_text(Some("@transactions"));
+        assert!(transactions.contains("  MULTI\n") && transactions.contains("  DISCARD\n"));
+        assert!(!transactions.contains("GET"));
+        assert!(help_text(Some("@nope")).starts_with("No such group"));
+        assert!(help_text(Some("NOPE")).starts_with("No help for 'NOPE'"));
+        assert!(help_text(None).contains("help @<group>"));
+    }
+}
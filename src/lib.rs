@@ -0,0 +1,19 @@
+//! r-cli 的客户端库：连接、RESP 编解码、命令表以及类型化的 `Client`。
+//!
+//! `r-cli` 可执行文件只是这个库的一个使用者。
+
+pub mod anet;
+pub mod client;
+pub mod error;
+pub mod help;
+pub mod redis_cli;
+pub mod util;
+
+#[allow(dead_code)]
+mod adlist;
+#[allow(dead_code)]
+mod zipmap;
+
+pub use client::{Client, Subscriber};
+pub use error::RedisError;
+pub use redis_cli::{read_reply, CommandType, Config, Connection, Protocol, PubSubMessage, RedisCommand, RedisValue, CMD_TABLE};
@@ -1,32 +1,66 @@
 use std::env;
-use crate::anet::{tcp_connect};
-use crate::redis_cli::{cli_send_command, init, repl, Config};
-
-mod redis_cli;
-mod anet;
-mod adlist;
-mod zipmap;
+use std::os::unix::ffi::OsStringExt;
+use std::io;
+use std::process::exit;
+use r_cli::redis_cli::{
+    cli_send_command, connect, exit_code, init, pipe_mode, repl, report_error, retry_with_backoff, usage, Config,
+    EXIT_CONNECTION,
+    EXIT_ERROR_REPLY, EXIT_OK,
+};
 
 fn main() {
-    // 1. 收集命令行参数
-    let args: Vec<String> = env::args().collect();
+    // 1. 收集命令行参数（命令参数保留原始字节，选项按 UTF-8 解析）
+    let raw_args: Vec<Vec<u8>> = env::args_os().map(|a| a.into_vec()).collect();
+    let args: Vec<String> = raw_args.iter().map(|a| String::from_utf8_lossy(a).into_owned()).collect();
 
     // 2. 配置
     let mut config = Config::new();
-    let parsed = config.parse_options(&args);
+    let parsed = match config.parse_options(&args) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            report_error(&err);
+            usage();
+            exit(exit_code(&err));
+        }
+    };
     let interactive = config.interactive;
 
     // 3. 连接与初始化
-    let mut stream = tcp_connect(config.hostip.as_str(), config.hostport).unwrap();
-    init(&mut config, &mut stream);
+    let connected = if config.wait_for_server { retry_with_backoff(|| connect(&config)) } else { connect(&config) };
+    let mut conn = match connected {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Could not connect to Redis at {}: {}", config.server_addr(), err);
+            exit(EXIT_CONNECTION);
+        }
+    };
+    if let Err(err) = init(&config, &mut conn) {
+        report_error(&err);
+        if !interactive {
+            exit(exit_code(&err));
+        }
+    }
+
+    // 4. pipe 模式：从标准输入批量导入
+    if config.pipe {
+        match pipe_mode(conn, io::stdin().lock()) {
+            Ok(stats) => {
+                println!("errors: {}, replies: {}", stats.errors, stats.replies);
+                exit(if stats.errors > 0 { EXIT_ERROR_REPLY } else { EXIT_OK });
+            }
+            Err(err) => {
+                report_error(&err);
+                exit(exit_code(&err));
+            }
+        }
+    }
 
-    // 4. 启动 REPL
+    // 5. 启动 REPL
     if (args.len() - parsed == 0) || interactive {
-        repl(&mut config, &mut stream);
+        repl(&mut config, &mut conn);
+        exit(EXIT_OK);
     }
 
-    // 5. 非交互式执行命令
-    cli_send_command(args[parsed..].iter().map(|s| s.as_str()).collect(), &config, &mut stream, false);
+    // 6. 非交互式执行命令
+    exit(cli_send_command(&raw_args[parsed..], &mut config, &mut conn, false));
 }
-
-
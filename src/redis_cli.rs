@@ -1,16 +1,82 @@
-use std::io::{self, BufRead, BufReader, Read, Write};
-use std::net::TcpStream;
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, StdinLock, Write};
+use std::net::Shutdown;
+use std::path::PathBuf;
 use std::process::exit;
-use crate::anet::resolve_host;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, Once};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::borrow::Cow;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use crate::anet::{
+    format_addr, recv_timeout, send_timeout, set_recv_buffer, set_send_buffer, tcp_connect, tcp_keep_alive,
+    tcp_no_delay, unix_connect, AddressFamily, Stream,
+};
+use crate::error::RedisError;
+use crate::help::{help_text, help_topics};
+use crate::util::string_match;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Config {
+    /// 主机名或 IP，连接时才解析，依次尝试解析出的每个地址
     pub hostip: String,
     pub hostport: u16,
+    /// Unix 域套接字路径，设置后忽略 `hostip` 与 `hostport`
+    pub hostsocket: Option<String>,
+    /// 解析主机名时优先尝试的地址族
+    pub address_family: AddressFamily,
     pub repeat: i64,
     pub dbnum: u8,
     pub auth: Option<String>,
     pub interactive: bool,
+    pub pipe: bool,
+    pub output: OutputFormat,
+    pub protocol: Protocol,
+    pub monitor: MonitorOptions,
+    /// 连接超时，`None` 表示一直等待
+    pub connect_timeout: Option<Duration>,
+    /// 读写超时，`None` 表示一直等待。BLPOP/BRPOP 在此基础上加上各自的阻塞时间
+    pub timeout: Option<Duration>,
+    /// TCP 连接是否关闭 Nagle 算法
+    pub tcp_nodelay: bool,
+    /// TCP keepalive 的空闲时间，`None` 表示不开启
+    pub keepalive: Option<Duration>,
+    /// TCP 发送缓冲区大小，`None` 表示使用系统默认值
+    pub send_buffer: Option<usize>,
+    /// TCP 接收缓冲区大小，`None` 表示使用系统默认值
+    pub recv_buffer: Option<usize>,
+    /// 启动时服务端不可达则按重连策略等待
+    pub wait_for_server: bool,
+}
+
+/// MONITOR 模式的过滤与统计选项
+#[derive(Debug, Clone, Default)]
+pub struct MonitorOptions {
+    /// 只显示该命令（不区分大小写）
+    pub command: Option<String>,
+    /// 只显示任一参数匹配该 glob 的命令
+    pub key_pattern: Option<String>,
+    /// 退出时打印每个命令的次数
+    pub stats: bool,
+}
+
+/// 回复的输出格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// 带 `(integer)`、`(error)` 等修饰的终端格式
+    Tty,
+    /// 不加任何修饰的原始内容
+    Raw,
+    Csv,
+    Json,
 }
 
 impl Config {
@@ -18,14 +84,31 @@ impl Config {
         Config {
             hostip: "127.0.0.1".to_string(),
             hostport: 6379,
+            hostsocket: None,
+            address_family: AddressFamily::Any,
             repeat: 1,
             dbnum: 0,
             auth: None,
             interactive: false,
+            pipe: false,
+            // 与 redis-cli 一致：标准输出不是终端时默认使用 raw 格式
+            output: if io::stdout().is_terminal() { OutputFormat::Tty } else { OutputFormat::Raw },
+            protocol: Protocol::MultiBulk,
+            monitor: MonitorOptions::default(),
+            connect_timeout: Some(Duration::from_secs(1)),
+            timeout: None,
+            wait_for_server: false,
+            tcp_nodelay: true,
+            // 与 redis-cli 一致，避免长时间空闲的交互会话被 NAT 静默断开
+            keepalive: Some(Duration::from_secs(15)),
+            send_buffer: None,
+            recv_buffer: None,
         }
     }
 
-    pub fn parse_options(&mut self, args: &Vec<String>) -> usize {
+    /// 解析命令行选项，返回第一个非选项参数的下标。选项值不合法时返回 `RedisError::Command`
+    pub fn parse_options(&mut self, args: &[String]) -> Result<usize, RedisError> {
+        let invalid = |what: &str, value: &str| RedisError::Command(format!("Invalid {}: {}", what, value));
         let argc = args.len();
         let mut i = 1;
 
@@ -33,66 +116,245 @@ impl Config {
             let lastarg = i == argc - 1;
             match args[i].as_str() {
                 "-h" if !lastarg => {
-                    let ip_or_host = args[i + 1].clone();
-                    match resolve_host(&ip_or_host) {
-                        Ok(ip) => {
-                            self.hostip = ip;
-                        }
-                        Err(err) => {
-                            eprintln!("{}", err);
-                            exit(1);
-                        }
-                    }
+                    self.hostip = args[i + 1].clone();
                     i += 2;
                 }
                 "-h" if lastarg => {
-                    usage();
+                    return Err(RedisError::Command("Missing hostname after -h".to_string()));
                 }
                 "-p" if !lastarg => {
-                    self.hostport = args[i + 1].parse().unwrap_or_else(|_| {
-                        eprintln!("Invalid port: {}", args[i + 1]);
-                        exit(1);
-                    });
+                    self.hostport = args[i + 1].parse().map_err(|_| invalid("port", &args[i + 1]))?;
+                    i += 2;
+                }
+                "-4" => {
+                    self.address_family = AddressFamily::Ipv4;
+                    i += 1;
+                }
+                "-6" => {
+                    self.address_family = AddressFamily::Ipv6;
+                    i += 1;
+                }
+                "-s" if !lastarg => {
+                    self.hostsocket = Some(args[i + 1].clone());
                     i += 2;
                 }
                 "-r" if !lastarg => {
-                    self.repeat = args[i + 1].parse().unwrap_or_else(|_| {
-                        eprintln!("Invalid repeat count: {}", args[i + 1]);
-                        exit(1);
-                    });
+                    self.repeat = args[i + 1].parse().map_err(|_| invalid("repeat count", &args[i + 1]))?;
                     i += 2;
                 }
                 "-n" if !lastarg => {
-                    self.dbnum = args[i + 1].parse().unwrap_or_else(|_| {
-                        eprintln!("Invalid db number: {}", args[i + 1]);
-                        exit(1);
-                    });
+                    self.dbnum = args[i + 1].parse().map_err(|_| invalid("db number", &args[i + 1]))?;
                     i += 2;
                 }
                 "-a" if !lastarg => {
+                    eprintln!("Warning: using a password with '-a' on the command line is not safe, it is visible in ps.");
+                    eprintln!("Use -u with a URI or the REDISCLI_AUTH environment variable instead.");
                     self.auth = Some(args[i + 1].to_string().clone());
                     i += 2;
                 }
+                "-u" if !lastarg => {
+                    self.apply_uri(&args[i + 1])?;
+                    i += 2;
+                }
                 "-i" => {
                     self.interactive = true;
                     i += 1;
                 }
+                "--pipe" => {
+                    self.pipe = true;
+                    i += 1;
+                }
+                "--protocol" if !lastarg => {
+                    self.protocol = match args[i + 1].as_str() {
+                        "multibulk" => Protocol::MultiBulk,
+                        "legacy" => Protocol::Legacy,
+                        other => return Err(invalid("protocol", &format!("{} (expected multibulk or legacy)", other))),
+                    };
+                    i += 2;
+                }
+                "-t" | "--timeout" if !lastarg => {
+                    let timeout = args[i + 1]
+                        .parse::<f64>()
+                        .ok()
+                        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                        .ok_or_else(|| invalid("timeout", &args[i + 1]))?;
+                    // 0 表示不限时
+                    let timeout = (!timeout.is_zero()).then_some(timeout);
+                    self.connect_timeout = timeout;
+                    self.timeout = timeout;
+                    i += 2;
+                }
+                "--no-tcp-nodelay" => {
+                    self.tcp_nodelay = false;
+                    i += 1;
+                }
+                "--keepalive" if !lastarg => {
+                    let secs: u64 = args[i + 1].parse().map_err(|_| invalid("keepalive interval", &args[i + 1]))?;
+                    // 0 表示不开启
+                    self.keepalive = (secs > 0).then(|| Duration::from_secs(secs));
+                    i += 2;
+                }
+                "--sndbuf" | "--rcvbuf" if !lastarg => {
+                    let size: usize = args[i + 1].parse().map_err(|_| invalid("buffer size", &args[i + 1]))?;
+                    if args[i] == "--sndbuf" {
+                        self.send_buffer = Some(size);
+                    } else {
+                        self.recv_buffer = Some(size);
+                    }
+                    i += 2;
+                }
+                "--wait-for-server" => {
+                    self.wait_for_server = true;
+                    i += 1;
+                }
+                "--monitor-cmd" if !lastarg => {
+                    self.monitor.command = Some(args[i + 1].clone());
+                    i += 2;
+                }
+                "--monitor-key" if !lastarg => {
+                    self.monitor.key_pattern = Some(args[i + 1].clone());
+                    i += 2;
+                }
+                "--monitor-stats" => {
+                    self.monitor.stats = true;
+                    i += 1;
+                }
+                "--raw" => {
+                    self.output = OutputFormat::Raw;
+                    i += 1;
+                }
+                "--no-raw" => {
+                    self.output = OutputFormat::Tty;
+                    i += 1;
+                }
+                "--csv" => {
+                    self.output = OutputFormat::Csv;
+                    i += 1;
+                }
+                "--json" => {
+                    self.output = OutputFormat::Json;
+                    i += 1;
+                }
                 _ => break,
             }
         }
-        i
+        if self.auth.is_none() {
+            self.auth = env::var("REDISCLI_AUTH").ok().filter(|auth| !auth.is_empty());
+        }
+        Ok(i)
+    }
+
+    /// 用于提示符与错误信息的服务端地址：`host:port` 或套接字路径
+    pub fn server_addr(&self) -> String {
+        match &self.hostsocket {
+            Some(path) => path.clone(),
+            None => format_addr(&self.hostip, self.hostport),
+        }
+    }
+
+    /// 按 `redis://[:password@]host[:port][/db]` 设置主机、端口、密码与数据库。
+    ///
+    /// 密码中的特殊字符可以用 `%XX` 转义；IPv6 地址写在方括号中，如 `redis://[::1]:6379`。
+    pub fn apply_uri(&mut self, uri: &str) -> Result<(), RedisError> {
+        let invalid = |reason: &str| RedisError::Command(format!("Invalid URI {}: {}", uri, reason));
+        let rest = uri.strip_prefix("redis://").ok_or_else(|| invalid("expected redis:// scheme"))?;
+        let (authority, db) = match rest.split_once('/') {
+            Some((authority, db)) => (authority, Some(db).filter(|db| !db.is_empty())),
+            None => (rest, None),
+        };
+        let (userinfo, hostport) = match authority.rsplit_once('@') {
+            Some((userinfo, hostport)) => (Some(userinfo), hostport),
+            None => (None, authority),
+        };
+        let (host, port) = match hostport.strip_prefix('[') {
+            Some(bracketed) => {
+                let (host, after) = bracketed.split_once(']').ok_or_else(|| invalid("unterminated '['"))?;
+                (host, after.strip_prefix(':'))
+            }
+            None => match hostport.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (hostport, None),
+            },
+        };
+
+        if !host.is_empty() {
+            self.hostip = host.to_string();
+        }
+        if let Some(port) = port {
+            self.hostport = port.parse().map_err(|_| invalid("bad port"))?;
+        }
+        if let Some(userinfo) = userinfo {
+            // 1.x 没有用户名，`user:password` 中只取密码
+            let password = userinfo.split_once(':').map_or(userinfo, |(_, password)| password);
+            self.auth = Some(percent_decode(password).ok_or_else(|| invalid("bad escape in password"))?);
+        }
+        if let Some(db) = db {
+            self.dbnum = db.parse().map_err(|_| invalid("bad db number"))?;
+        }
+        Ok(())
+    }
+}
+
+/// 解码 URI 中的 `%XX` 转义
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::new()
     }
 }
 
-fn usage() {
+/// 将选项说明输出到标准错误
+pub fn usage() {
     eprintln!("Usage: program [options]");
     eprintln!("  -h <hostname|ip>   Server hostname or IP (default 127.0.0.1)");
     eprintln!("  -p <port>          Server port (1-65535, default 6379)");
+    eprintln!("  -4                 Prefer IPv4 addresses when resolving the hostname");
+    eprintln!("  -6                 Prefer IPv6 addresses when resolving the hostname");
+    eprintln!("  -s <socket>        Server socket (overrides hostname and port)");
     eprintln!("  -r <repeat>        Repeat count");
     eprintln!("  -n <dbnum>         Database number");
-    eprintln!("  -a <password>      Password");
+    eprintln!("  -a <password>      Password (visible in ps; prefer -u or REDISCLI_AUTH)");
+    eprintln!("  -u <uri>           Server URI: redis://[:password@]host[:port][/db]");
     eprintln!("  -i                 Interactive mode");
-    exit(1);
+    eprintln!("  --pipe             Transfer raw RESP or text commands from stdin to server");
+    eprintln!("  -t <seconds>       Connect, read and write timeout, 0 waits forever (default: 1s connect only)");
+    eprintln!("  --timeout <secs>   Same as -t");
+    eprintln!("  --no-tcp-nodelay   Keep Nagle's algorithm enabled on the TCP connection");
+    eprintln!("  --keepalive <secs> TCP keepalive idle time, 0 disables (default 15)");
+    eprintln!("  --sndbuf <bytes>   TCP send buffer size");
+    eprintln!("  --rcvbuf <bytes>   TCP receive buffer size");
+    eprintln!("  --wait-for-server  Retry the initial connection with backoff until the server is up");
+    eprintln!("  --protocol <mode>  multibulk (default) or legacy inline/bulk framing for 1.x servers");
+    eprintln!("  --monitor-cmd <c>  MONITOR: only show command <c>");
+    eprintln!("  --monitor-key <p>  MONITOR: only show commands with an argument matching glob <p>");
+    eprintln!("  --monitor-stats    MONITOR: print per-command counts on exit");
+    eprintln!("  --raw              Print raw replies (default when stdout is not a tty)");
+    eprintln!("  --no-raw           Force formatted output even when stdout is not a tty");
+    eprintln!("  --csv              Print replies as CSV");
+    eprintln!("  --json             Print replies as JSON");
+    eprintln!();
+    eprintln!("Exit codes (non-interactive mode):");
+    eprintln!("  0  success");
+    eprintln!("  1  the server replied with an error");
+    eprintln!("  2  unknown command, wrong number of arguments or bad option");
+    eprintln!("  3  cannot resolve or connect to the server");
+    eprintln!("  4  protocol or I/O error");
 }
 
 #[derive(Debug, Clone)]
@@ -100,7 +362,11 @@ pub struct RedisCommand<'a> {
     pub name: &'a str,
     pub arity: i32,
     pub flags: CommandType,
-    pub argv: Vec<&'a str>,
+    /// 固定参数的名字
+    pub params: &'a [&'a str],
+    /// 可变参数的名字：第一个可重复，其余跟在重复部分之后（如 BLPOP 的 `keys, timeout`）
+    pub variadic: &'a [&'a str],
+    pub argv: Vec<Vec<u8>>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -110,296 +376,2316 @@ pub enum CommandType {
     MultiBulk,
 }
 
+/// 发送命令时使用的协议
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Protocol {
+    /// 所有命令都用 multi-bulk 发送
+    #[default]
+    MultiBulk,
+    /// 按命令表中的 `CommandType` 使用 inline、bulk 或 multi-bulk，用于 multi-bulk 之前的服务端
+    Legacy,
+}
+
 impl<'a> RedisCommand<'a> {
-    pub fn build(args: Vec<&'a str>) -> Result<Self, String> {
+    pub fn build<T: AsRef<[u8]>>(args: &[T]) -> Result<Self, RedisError> {
+        let name = args.first().ok_or_else(|| RedisError::Command("empty command".to_string()))?.as_ref();
         let cmd = CMD_TABLE
             .iter()
-            .find(|c| c.name.eq_ignore_ascii_case(args[0]))
-            .ok_or_else(|| format!("Unknown command: {}", args[0]))?;
+            .find(|c| c.name.as_bytes().eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                RedisError::Command(format!(
+                    "Unknown command: {}, type \"help\" for a list of help topics",
+                    String::from_utf8_lossy(name)
+                ))
+            })?;
 
         let argc = args.len() as i32;
         if (cmd.arity > 0 && cmd.arity != argc) || (cmd.arity < 0 && argc < -cmd.arity) {
-            return Err(format!("Wrong number of arguments for '{}'", cmd.name));
+            return Err(RedisError::Command(format!(
+                "Wrong number of arguments for '{}', usage: {} (see \"help {}\")",
+                cmd.name,
+                cmd.syntax(),
+                cmd.name
+            )));
         }
 
         Ok(RedisCommand {
             name: cmd.name,
             arity: cmd.arity,
             flags: cmd.flags,
-            argv: args[1..].to_vec(),
+            params: cmd.params,
+            variadic: cmd.variadic,
+            argv: args[1..].iter().map(|a| a.as_ref().to_vec()).collect(),
         })
     }
 
-    pub fn to_resp(&self) -> String {
-        let args = self.argv.to_vec();
+    /// 参数提示，每项对应一个或一组参数，如 BLPOP 为 `key`、`[key ...]`、`timeout`。
+    ///
+    /// 可变参数名去掉复数后缀作为单个参数名，`key_values` 展开为 `key value`；
+    /// arity 要求至少出现一次时先列出一次必需的，再列出可重复的部分。
+    pub fn arg_hints(&self) -> Vec<String> {
+        let mut hints: Vec<String> = self.params.iter().map(|p| p.to_string()).collect();
+        if let Some((repeated, tail)) = self.variadic.split_first() {
+            let single = repeated.strip_suffix('s').unwrap_or(repeated).replace('_', " ");
+            let required = self.arity.unsigned_abs() as usize - 1;
+            if required > self.params.len() + tail.len() {
+                hints.push(single.clone());
+            }
+            hints.push(format!("[{} ...]", single));
+            hints.extend(tail.iter().map(|p| p.to_string()));
+        }
+        hints
+    }
+
+    /// 命令的完整用法，如 `ZADD key score member`
+    pub fn syntax(&self) -> String {
+        let mut syntax = self.name.to_string();
+        for hint in self.arg_hints() {
+            syntax.push(' ');
+            syntax += &hint;
+        }
+        syntax
+    }
+
+    /// 能否用 inline 或 1.x bulk 帧无歧义地发送。
+    ///
+    /// 这两种帧中以空格分隔的参数不能为空，也不能包含空白字符；bulk 命令的最后一个参数
+    /// 带长度前缀，不受限制。不满足时 `encode` 会改用 multi-bulk。
+    pub fn inline_safe(&self) -> bool {
+        let spaced = match self.flags {
+            CommandType::Inline => &self.argv[..],
+            CommandType::Bulk => &self.argv[..self.argv.len().saturating_sub(1)],
+            CommandType::MultiBulk => return true,
+        };
+        spaced.iter().all(|arg| !arg.is_empty() && !arg.iter().any(|b| b.is_ascii_whitespace()))
+    }
+
+    /// BLPOP/BRPOP 的最后一个参数为阻塞秒数，返回该秒数，0 表示一直阻塞。其它命令返回 `None`
+    pub fn blocking_secs(&self) -> Option<u64> {
+        match self.name {
+            "BLPOP" | "BRPOP" => self.argv.last().and_then(|secs| std::str::from_utf8(secs).ok()?.parse().ok()),
+            _ => None,
+        }
+    }
+
+    /// 重复执行与执行一次效果相同的命令，连接中断后可以安全重试。
+    ///
+    /// FLUSHDB、FLUSHALL 虽然幂等但具有破坏性，重连后的数据库未必是用户以为的那个，不自动重试
+    pub fn is_idempotent(&self) -> bool {
+        matches!(
+            self.name,
+            "GET" | "SET" | "SUBSTR" | "DEL" | "EXISTS" | "LLEN" | "LINDEX" | "LSET" | "LRANGE" | "SADD" | "SREM"
+                | "SISMEMBER" | "SCARD" | "SRANDMEMBER" | "SINTER" | "SINTERSTORE" | "SUNION" | "SUNIONSTORE"
+                | "SDIFF" | "SDIFFSTORE" | "SMEMBERS" | "ZADD" | "ZREM" | "ZREMRANGEBYSCORE" | "ZRANGE" | "ZRANK"
+                | "ZREVRANK" | "ZRANGEBYSCORE" | "ZCOUNT" | "ZREVRANGE" | "ZCARD" | "ZSCORE" | "RANDOMKEY"
+                | "SELECT" | "KEYS" | "DBSIZE" | "PING" | "ECHO" | "LASTSAVE" | "TYPE" | "SORT" | "INFO" | "MGET"
+                | "EXPIREAT" | "TTL" | "MSET" | "HSET" | "HGET" | "HDEL" | "HLEN" | "HKEYS" | "HVALS" | "HGETALL"
+                | "HEXISTS"
+        )
+    }
+
+    /// SELECT 的目标数据库编号，其它命令返回 `None`
+    pub fn select_target(&self) -> Option<u8> {
+        match self.name {
+            "SELECT" => std::str::from_utf8(self.argv.first()?).ok()?.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// 用于显示的命令行，含空白或不可打印字符的参数加引号
+    pub fn display(&self) -> String {
+        let mut s = self.name.to_string();
+        for arg in &self.argv {
+            s.push(' ');
+            if arg.is_empty() || arg.iter().any(|b| !b.is_ascii_graphic() || *b == b'"' || *b == b'\'') {
+                s += &repr_bytes(arg);
+            } else {
+                s += &String::from_utf8_lossy(arg);
+            }
+        }
+        s
+    }
+
+    /// 按 multi-bulk 编码，所有服务端都能正确解析
+    pub fn to_resp(&self) -> Vec<u8> {
+        self.encode(Protocol::MultiBulk)
+    }
+
+    /// 按指定协议编码。`Protocol::Legacy` 下依据命令的 `flags` 选择 inline、bulk 或 multi-bulk，
+    /// 参数会造成歧义时改用 multi-bulk
+    pub fn encode(&self, protocol: Protocol) -> Vec<u8> {
+        let flags = match protocol {
+            Protocol::MultiBulk => CommandType::MultiBulk,
+            Protocol::Legacy if !self.inline_safe() => CommandType::MultiBulk,
+            Protocol::Legacy => self.flags,
+        };
 
-        match self.flags {
+        match flags {
             CommandType::MultiBulk => {
-                let mut s = format!("*{}\r\n", self.argv.len() + 1);
-                s += &format!("${}\r\n{}\r\n", self.name.len(), self.name);
-                for arg in args {
-                    s += &format!("${}\r\n{}\r\n", arg.len(), arg);
+                let mut s = format!("*{}\r\n", self.argv.len() + 1).into_bytes();
+                push_bulk(&mut s, self.name.as_bytes());
+                for arg in &self.argv {
+                    push_bulk(&mut s, arg);
                 }
                 s
             }
             CommandType::Bulk => {
-                let mut s = format!("*{}\r\n", args.len() + 1);
-                s += &format!("${}\r\n{}\r\n", self.name.len(), self.name);
-                for arg in args {
-                    s += &format!("${}\r\n{}\r\n", arg.len(), arg);
+                // 1.x 的 bulk 命令：最后一个参数以 `长度\r\n内容\r\n` 的形式发送
+                let (last, rest) = self.argv.split_last().expect("bulk commands take at least one argument");
+                let mut s = self.name.as_bytes().to_vec();
+                for arg in rest {
+                    s.push(b' ');
+                    s.extend_from_slice(arg);
                 }
+                s.extend_from_slice(format!(" {}\r\n", last.len()).as_bytes());
+                s.extend_from_slice(last);
+                s.extend_from_slice(b"\r\n");
                 s
             }
             CommandType::Inline => {
-                let mut s = self.name.to_string();
-                for arg in args {
-                    s += " ";
-                    s += arg;
+                let mut s = self.name.as_bytes().to_vec();
+                for arg in &self.argv {
+                    s.push(b' ');
+                    s.extend_from_slice(arg);
                 }
-                s += "\r\n";
+                s.extend_from_slice(b"\r\n");
                 s
             }
         }
     }
 }
 
+/// 追加一个 `$len\r\npayload\r\n` 参数
+fn push_bulk(buf: &mut Vec<u8>, arg: &[u8]) {
+    buf.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+    buf.extend_from_slice(arg);
+    buf.extend_from_slice(b"\r\n");
+}
+
+// ===================== 连接 =====================
+
+/// MULTI 之后入队的一条命令
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueuedCommand {
+    /// 用于显示的命令行
+    pub display: String,
+    /// 入队的是 SELECT 时为目标数据库，EXEC 成功后据此更新当前数据库
+    pub select: Option<u8>,
+}
+
+/// 与服务端的连接，读写各持有一个长期存在的缓冲区。
+///
+/// 读缓冲区在多次回复之间复用，流水线回复中已读入缓冲区的字节不会丢失。
+pub struct Connection {
+    reader: BufReader<Stream>,
+    writer: BufWriter<Stream>,
+    /// MULTI 之后已入队的命令，不在事务中时为 `None`
+    multi: Option<Vec<QueuedCommand>>,
+    /// 已进入 MONITOR 或订阅状态，服务端只推送消息、不再接受普通命令
+    streaming: bool,
+    /// 读超时或 I/O 错误后连接已被关闭，需要重新连接
+    broken: bool,
+}
+
+impl Connection {
+    /// 在 TCP 连接或 Unix 域套接字上建立连接
+    pub fn new(stream: impl Into<Stream>) -> Result<Self, RedisError> {
+        let stream = stream.into();
+        let write_half = stream.try_clone()?;
+        Ok(Connection {
+            reader: BufReader::new(stream),
+            writer: BufWriter::new(write_half),
+            multi: None,
+            streaming: false,
+            broken: false,
+        })
+    }
+
+    /// 写入写缓冲区，不立即发送
+    pub fn write(&mut self, buf: &[u8]) -> Result<(), RedisError> {
+        Ok(self.writer.write_all(buf)?)
+    }
+
+    /// 将写缓冲区中的数据发送出去
+    pub fn flush(&mut self) -> Result<(), RedisError> {
+        Ok(self.writer.flush()?)
+    }
+
+    /// 写入并立即发送
+    pub fn send(&mut self, buf: &[u8]) -> Result<(), RedisError> {
+        self.write(buf)?;
+        self.flush()
+    }
+
+    /// 从读缓冲区读取一个完整回复
+    pub fn read_reply(&mut self) -> Result<RedisValue, RedisError> {
+        read_reply(&mut self.reader)
+    }
+
+    /// 发送一条命令并读取回复。
+    ///
+    /// BLPOP/BRPOP 读回复时的超时为阻塞时间加上连接原有的读超时，阻塞时间为 0 时一直等待。
+    /// 出错后事务状态被重置，因为服务端的状态已不可知。读超时后迟到的回复会被下一条命令误读，
+    /// 因此读超时或 I/O 错误后连接随即关闭，之后的读写都返回 I/O 错误，需要重新连接。
+    pub fn request(&mut self, rc: &RedisCommand, protocol: Protocol) -> Result<RedisValue, RedisError> {
+        let result = self.send(&rc.encode(protocol)).and_then(|_| match rc.blocking_secs() {
+            Some(secs) => {
+                let timeout = self.reader.get_ref().read_timeout()?;
+                self.set_read_timeout(timeout.filter(|_| secs > 0).map(|t| t + Duration::from_secs(secs)))?;
+                let reply = self.read_reply();
+                self.set_read_timeout(timeout)?;
+                reply
+            }
+            None => self.read_reply(),
+        });
+        if let Err(err) = &result {
+            self.multi = None;
+            if err.is_timeout() || err.is_io() {
+                self.abandon();
+            }
+        }
+        result
+    }
+
+    /// 丢弃已缓冲的部分回复并关闭 socket
+    fn abandon(&mut self) {
+        let buffered = self.reader.buffer().len();
+        self.reader.consume(buffered);
+        let _ = self.reader.get_ref().shutdown(Shutdown::Both);
+        self.broken = true;
+    }
+
+    /// 连接是否已因读超时或 I/O 错误被关闭
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// 设置读超时，`None` 表示一直等待
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), RedisError> {
+        recv_timeout(self.reader.get_ref(), timeout)
+    }
+
+    /// 处于 MULTI 事务中时返回已入队的命令
+    pub fn queued(&self) -> Option<&[QueuedCommand]> {
+        self.multi.as_deref()
+    }
+
+    /// 连接是否处于 MONITOR 或订阅状态
+    pub fn is_streaming(&self) -> bool {
+        self.streaming
+    }
+
+    /// 根据命令及其回复更新事务状态。EXEC 结束事务时返回入队过的命令，用于与回复配对
+    pub fn track_transaction(&mut self, rc: &RedisCommand, reply: &RedisValue) -> Option<Vec<QueuedCommand>> {
+        match (rc.name, reply) {
+            ("MULTI", RedisValue::Status(_)) => self.multi = Some(Vec::new()),
+            ("EXEC", _) => return self.multi.take(),
+            ("DISCARD", _) => self.multi = None,
+            (_, RedisValue::Status(s)) if s == "QUEUED" => {
+                if let Some(queued) = &mut self.multi {
+                    queued.push(QueuedCommand { display: rc.display(), select: rc.select_target() });
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// 复制底层 socket 句柄，可用于从其它线程关闭连接
+    pub fn try_clone_stream(&self) -> Result<Stream, RedisError> {
+        Ok(self.reader.get_ref().try_clone()?)
+    }
+
+    /// 拆分为读、写两半，供读写并行的场景（如 pipe 模式）使用
+    pub fn into_split(self) -> (BufReader<Stream>, BufWriter<Stream>) {
+        (self.reader, self.writer)
+    }
+}
+
+// ===================== 退出码 =====================
+// 非交互模式下进程的退出码
+
+/// 命令执行成功
+pub const EXIT_OK: i32 = 0;
+/// 服务端返回了错误回复（`-ERR ...`），包括 AUTH、SELECT 失败
+pub const EXIT_ERROR_REPLY: i32 = 1;
+/// 未知命令、参数个数错误或命令行选项错误
+pub const EXIT_USAGE: i32 = 2;
+/// 无法解析主机名或无法连接服务端
+pub const EXIT_CONNECTION: i32 = 3;
+/// 协议错误或连接中途出现 I/O 错误
+pub const EXIT_PROTOCOL: i32 = 4;
+
 // ===================== RESP 命令处理 =====================
-pub fn cli_send_command(args: Vec<&str>, config: &Config, stream: &mut TcpStream, quiet: bool) {
+
+/// 错误对应的退出码
+pub fn exit_code(err: &RedisError) -> i32 {
+    match err {
+        RedisError::Server(_) => EXIT_ERROR_REPLY,
+        RedisError::Command(_) => EXIT_USAGE,
+        RedisError::Resolve(_) => EXIT_CONNECTION,
+        RedisError::Timeout(_) | RedisError::Io(_) | RedisError::Protocol(_) => EXIT_PROTOCOL,
+    }
+}
+
+/// 将错误输出到标准错误，错误回复沿用 `(error)` 前缀
+pub fn report_error(err: &RedisError) {
+    match err {
+        RedisError::Server(msg) => eprintln!("(error) {}", msg),
+        _ => eprintln!("{}", err),
+    }
+}
+
+/// 构建并发送命令（按 `-r` 重复），打印回复，返回退出码
+pub fn cli_send_command<T: AsRef<[u8]>>(args: &[T], config: &mut Config, conn: &mut Connection, quiet: bool) -> i32 {
     if args.is_empty() {
-        return;
+        return EXIT_OK;
+    }
+    if args[0].as_ref().eq_ignore_ascii_case(b"help") {
+        let topic = args.get(1).map(|t| String::from_utf8_lossy(t.as_ref()).into_owned());
+        print!("{}", help_text(topic.as_deref()));
+        return EXIT_OK;
     }
 
     let rc = match RedisCommand::build(args) {
         Ok(cmd) => cmd,
         Err(err) => {
-            eprintln!("{}", err);
-            return;
+            report_error(&err);
+            return exit_code(&err);
         }
     };
 
-    let cmd = rc.to_resp();
+    // 上一条命令超时或连接中断后连接已被关闭，在发送下一条命令前才重连，单条命令模式出错后直接退出
+    if conn.is_broken()
+        && let Err(err) = reconnect(config, conn)
+    {
+        report_error(&err);
+        return exit_code(&err);
+    }
+
+    let streamed = match rc.name {
+        "MONITOR" => Some(monitor(&rc, config, conn, quiet)),
+        "SUBSCRIBE" | "UNSUBSCRIBE" | "PSUBSCRIBE" | "PUNSUBSCRIBE" => Some(subscribe(&rc, config, conn, quiet)),
+        _ => None,
+    };
+    if let Some(result) = streamed {
+        return match result {
+            Ok(()) => EXIT_OK,
+            Err(err) => {
+                report_error(&err);
+                exit_code(&err)
+            }
+        };
+    }
+
+    let mut code = EXIT_OK;
     for _ in 0..config.repeat {
-        if let Err(err) = stream.write_all(cmd.as_bytes()) {
-            eprintln!("Failed to send command '{}': {}", rc.name, err);
+        let in_transaction = conn.queued().is_some();
+        let mut result = execute(&rc, config, conn, quiet);
+        // 服务端重启或关闭了空闲连接：只有幂等命令立即重连并重试，事务中的命令随事务一起丢失；
+        // 其它命令的连接留到下一条命令前再重连
+        if let Err(err) = &result
+            && err.is_io()
+            && rc.is_idempotent()
+            && !in_transaction
+            && reconnect(config, conn).is_ok()
+        {
+            result = execute(&rc, config, conn, quiet);
+        }
+        match result {
+            Ok(selected) => {
+                // 记录当前数据库，提示符与重连后的 SELECT 都以它为准；事务中的 SELECT 在 EXEC 后才生效
+                if let Some(db) = selected {
+                    config.dbnum = db;
+                }
+            }
+            Err(err) => {
+                // 非 quiet 时错误回复已经作为回复打印过了
+                if quiet || !err.is_server() {
+                    report_error(&err);
+                }
+                // 保留第一个错误的退出码，之后的成功不会把它覆盖
+                if code == EXIT_OK {
+                    code = exit_code(&err);
+                }
+                if !err.is_server() {
+                    break;
+                }
+            }
+        }
+    }
+    code
+}
+
+/// 发送一条命令并读取回复，`quiet` 为 false 时打印回复。错误回复转换为 `RedisError::Server`。
+///
+/// 命令切换了数据库时（SELECT，或包含 SELECT 的 EXEC）返回新的数据库编号
+fn execute(rc: &RedisCommand, config: &Config, conn: &mut Connection, quiet: bool) -> Result<Option<u8>, RedisError> {
+    let reply = conn.request(rc, config.protocol)?;
+    let queued = conn.track_transaction(rc, &reply);
+    if !quiet {
+        let out = match (&queued, config.output) {
+            (Some(queued), OutputFormat::Tty) => format_exec_reply(queued, &reply).into_bytes(),
+            _ => format_output(&reply, config.output),
+        };
+        let mut stdout = io::stdout().lock();
+        let _ = stdout.write_all(&out);
+        let _ = stdout.flush();
+    }
+    match reply {
+        RedisValue::Error(err) => Err(RedisError::Server(err)),
+        reply => Ok(selected_db(rc, &reply, queued.as_deref())),
+    }
+}
+
+/// 命令执行后所在的数据库：成功的 SELECT，或 EXEC 中最后一条回复 `+OK` 的 SELECT
+fn selected_db(rc: &RedisCommand, reply: &RedisValue, queued: Option<&[QueuedCommand]>) -> Option<u8> {
+    let ok = |reply: &RedisValue| matches!(reply, RedisValue::Status(s) if s == "OK");
+    match (queued, reply) {
+        (Some(queued), RedisValue::Array(items)) => {
+            queued.iter().zip(items).rev().find_map(|(cmd, item)| cmd.select.filter(|_| ok(item)))
         }
-        cli_read_reply(stream, quiet);
+        (None, reply) if ok(reply) => rc.select_target(),
+        _ => None,
     }
 }
 
-pub fn select_db(dbnum: u8, stream: &mut TcpStream) -> Result<(), String> {
-    if dbnum == 0 {
+/// 切换到 `config.dbnum` 指定的数据库
+pub fn select_db(config: &Config, conn: &mut Connection) -> Result<(), RedisError> {
+    if config.dbnum == 0 {
         return Ok(());
     }
 
-    let cmd = format!("SELECT {}\r\n", dbnum);
-    stream
-        .write_all(cmd.as_bytes())
-        .map_err(|e| format!("Failed to send SELECT command: {}", e))?;
+    let dbnum = config.dbnum.to_string();
+    let rc = RedisCommand::build(&["SELECT", dbnum.as_str()])?;
+    execute(&rc, config, conn, true).map(|_| ())
+}
+
+// ===================== 重连 =====================
+
+/// 连接的最多尝试次数
+const RETRY_ATTEMPTS: u32 = 8;
+/// 第一次重试前的等待时间，之后每次翻倍
+const RETRY_INITIAL_DELAY: Duration = Duration::from_millis(100);
+/// 两次重试之间的最长等待时间
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(3);
 
-    cli_read_reply(stream, false)
+/// 按指数退避重试 `f`，只重试 I/O 错误与超时（如服务端尚未启动），其它错误直接返回
+pub fn retry_with_backoff<T>(f: impl FnMut() -> Result<T, RedisError>) -> Result<T, RedisError> {
+    backoff(f, |err| err.is_io() || err.is_timeout())
 }
 
-// ===================== RESP 解析 =====================
+/// 按指数退避重试 `f`，`retryable` 返回 false 的错误直接返回
+fn backoff<T>(
+    mut f: impl FnMut() -> Result<T, RedisError>,
+    retryable: impl Fn(&RedisError) -> bool,
+) -> Result<T, RedisError> {
+    let mut delay = RETRY_INITIAL_DELAY;
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Err(err) if attempt < RETRY_ATTEMPTS && retryable(&err) => {
+                thread::sleep(delay);
+                delay = (delay * 2).min(RETRY_MAX_DELAY);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
 
-/// 核心读取函数，处理所有 Redis 回复类型
-pub fn cli_read_reply(stream: &mut TcpStream, quiet: bool) -> Result<(), String> {
-    let mut reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
-    read_reply(&mut reader, quiet)
+/// 重新连接并重放 AUTH 与 SELECT，成功后替换 `conn`。
+///
+/// 只重试 I/O 错误：服务端无响应导致的超时重试也无济于事，只会让 `-t` 的限时成倍延长
+pub fn reconnect(config: &Config, conn: &mut Connection) -> Result<(), RedisError> {
+    *conn = backoff(
+        || {
+            let mut conn = connect(config)?;
+            init(config, &mut conn)?;
+            Ok(conn)
+        },
+        RedisError::is_io,
+    )?;
+    Ok(())
 }
 
-/// 内部递归读取函数
-fn read_reply(reader: &mut BufReader<TcpStream>, quiet: bool) -> Result<(), String> {
-    let mut first_byte = [0u8; 1];
-    reader.read_exact(&mut first_byte).map_err(|e| e.to_string())?;
+// ===================== MONITOR =====================
 
-    match first_byte[0] as char {
-        '+' => { cli_read_single_line_reply(reader, quiet)?; }
-        '-' => {
-            print!("(error) ");
-            cli_read_single_line_reply(reader, quiet)?;
-        }
-        ':' => {
-            print!("(integer) ");
-            cli_read_single_line_reply(reader, quiet)?;
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static INTERRUPT_TARGET: Mutex<Option<Stream>> = Mutex::new(None);
+static INTERRUPT_HANDLER: Once = Once::new();
+
+/// 登记的连接在 guard 释放时注销，之后的 Ctrl-C 恢复为退出进程
+#[must_use]
+pub struct InterruptGuard(());
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        if let Ok(mut target) = INTERRUPT_TARGET.lock() {
+            target.take();
         }
-        '$' => { cli_read_bulk_reply(reader, quiet)?; }
-        '*' => { cli_read_multi_bulk_reply(reader, quiet)?; }
-        other => return Err(format!("protocol error, got '{}' as reply type byte", other)),
     }
+}
 
-    Ok(())
+/// 让 Ctrl-C 关闭 `conn` 的读端，阻塞在读取上的流式循环随即读到 EOF 并正常结束。
+///
+/// 只在返回的 guard 存活期间有效；没有登记连接时 Ctrl-C 按默认行为退出进程。
+pub fn interrupt_on_ctrl_c(conn: &Connection) -> Result<InterruptGuard, RedisError> {
+    INTERRUPT_HANDLER.call_once(|| {
+        let _ = ctrlc::set_handler(|| match INTERRUPT_TARGET.lock().ok().and_then(|mut t| t.take()) {
+            Some(stream) => {
+                INTERRUPTED.store(true, Ordering::SeqCst);
+                let _ = stream.shutdown(Shutdown::Read);
+            }
+            None => exit(130),
+        });
+    });
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    *INTERRUPT_TARGET.lock().unwrap() = Some(conn.try_clone_stream()?);
+    Ok(InterruptGuard(()))
 }
 
-/// 读取单行回复（+、-、:）
-fn cli_read_single_line_reply(reader: &mut BufReader<TcpStream>, quiet: bool) -> Result<String, String> {
-    let line = read_line(reader)?;
-    if !quiet {
-        println!("{}", line);
+/// 流式读取时读到 EOF 是否由 Ctrl-C 引起
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// 发送 MONITOR 并持续打印服务端推送的命令，直到 Ctrl-C。连接断开按错误返回。
+///
+/// `quiet` 为 true 时不打印回复与推送的命令，`--monitor-stats` 的统计照常输出
+fn monitor(rc: &RedisCommand, config: &Config, conn: &mut Connection, quiet: bool) -> Result<(), RedisError> {
+    execute(rc, config, conn, quiet)?;
+    conn.streaming = true;
+    let _interrupt = interrupt_on_ctrl_c(conn)?;
+    // 输出可能长时间没有新内容，不受读超时限制
+    conn.set_read_timeout(None)?;
+
+    let options = &config.monitor;
+    let mut counts: HashMap<String, u64> = HashMap::new();
+    let mut out = io::stdout();
+    let result = loop {
+        let reply = match conn.read_reply() {
+            Ok(reply) => reply,
+            Err(_) if interrupted() => break Ok(()),
+            Err(err) => break Err(err),
+        };
+        let Some(args) = monitor_args(&reply) else { continue };
+        if args.is_empty() {
+            continue;
+        }
+        if let Some(cmd) = &options.command
+            && !args[0].eq_ignore_ascii_case(cmd.as_bytes())
+        {
+            continue;
+        }
+        if let Some(pattern) = &options.key_pattern
+            && !args[1..].iter().any(|arg| string_match(pattern.as_bytes(), arg, false))
+        {
+            continue;
+        }
+
+        *counts.entry(String::from_utf8_lossy(&args[0]).to_uppercase()).or_insert(0) += 1;
+        if quiet {
+            continue;
+        }
+        let line = match &reply {
+            RedisValue::Status(line) => line.clone(),
+            _ => args.iter().map(|a| repr_bytes(a)).collect::<Vec<_>>().join(" "),
+        };
+        let _ = writeln!(out, "{}", line);
+        let _ = out.flush();
+    };
+
+    if options.stats {
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        println!("{:<20} calls", "command");
+        for (cmd, n) in counts {
+            println!("{:<20} {}", cmd, n);
+        }
     }
-    Ok(line)
+    result
 }
 
-/// 读取 bulk 回复 ($)
-fn cli_read_bulk_reply(reader: &mut BufReader<TcpStream>, quiet: bool) -> Result<Option<String>, String> {
-    let len_line = read_line(reader)?;
-    let bulklen: isize = len_line.parse().map_err(|_| "Invalid bulk length".to_string())?;
-    if bulklen == -1 {
-        if !quiet { println!("(nil)"); }
-        return Ok(None);
+/// 取出 MONITOR 推送的命令及参数。
+///
+/// 1.x 服务端以 multi-bulk 原样转发命令；2.x 起为
+/// `+1339518083.107412 [0 127.0.0.1:60866] "keys" "*"` 形式的状态行
+fn monitor_args(reply: &RedisValue) -> Option<Vec<Vec<u8>>> {
+    match reply {
+        RedisValue::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                RedisValue::Bulk(b) => Some(b.clone()),
+                _ => None,
+            })
+            .collect(),
+        RedisValue::Status(line) => {
+            let rest = line.split_once(' ').map(|(_, rest)| rest)?;
+            let rest = match rest.strip_prefix('[') {
+                Some(bracketed) => bracketed.split_once("] ").map(|(_, rest)| rest)?,
+                None => rest,
+            };
+            split_args(rest.as_bytes()).ok()
+        }
+        _ => None,
     }
+}
 
-    let mut buf = vec![0u8; bulklen as usize + 2]; // +2 for \r\n
-    reader.read_exact(&mut buf).map_err(|e| e.to_string())?;
-    let content = String::from_utf8_lossy(&buf[..bulklen as usize]).to_string();
-    if !quiet { println!("{}", content); }
-    Ok(Some(content))
+// ===================== 发布订阅 =====================
+
+/// 订阅状态下服务端推送的消息
+#[derive(Debug, Clone, PartialEq)]
+pub enum PubSubMessage {
+    /// `message`：订阅的频道收到消息
+    Message { channel: Vec<u8>, payload: Vec<u8> },
+    /// `pmessage`：订阅的模式匹配到某个频道的消息
+    PMessage { pattern: Vec<u8>, channel: Vec<u8>, payload: Vec<u8> },
+    /// `subscribe`、`unsubscribe`、`psubscribe`、`punsubscribe` 的确认，`count` 为剩余订阅数
+    Subscription { kind: String, channel: Option<Vec<u8>>, count: i64 },
 }
 
-/// 读取 multi-bulk 回复 (*)
-fn cli_read_multi_bulk_reply(reader: &mut BufReader<TcpStream>, quiet: bool) -> Result<(), String> {
-    let len_line = read_line(reader)?;
-    let count: isize = len_line.parse().map_err(|_| "Invalid multi-bulk count".to_string())?;
-    if count == -1 {
-        if !quiet { println!("(nil)"); }
-        return Ok(());
+impl PubSubMessage {
+    /// 从推送帧解析，不是合法的推送帧时返回 `None`
+    pub fn from_reply(reply: &RedisValue) -> Option<Self> {
+        let RedisValue::Array(items) = reply else { return None };
+        let bulk = |i: usize| match items.get(i) {
+            Some(RedisValue::Bulk(b)) => Some(b.clone()),
+            _ => None,
+        };
+        let kind = String::from_utf8(bulk(0)?).ok()?;
+        match (kind.as_str(), items.len()) {
+            ("message", 3) => Some(PubSubMessage::Message { channel: bulk(1)?, payload: bulk(2)? }),
+            ("pmessage", 4) => Some(PubSubMessage::PMessage { pattern: bulk(1)?, channel: bulk(2)?, payload: bulk(3)? }),
+            ("subscribe" | "unsubscribe" | "psubscribe" | "punsubscribe", 3) => match items[2] {
+                RedisValue::Integer(count) => Some(PubSubMessage::Subscription { kind, channel: bulk(1), count }),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// 执行 (P)SUBSCRIBE / (P)UNSUBSCRIBE 并打印推送帧。
+///
+/// 订阅命令会一直读取并打印消息，直到 Ctrl-C、连接关闭或订阅数归零；
+/// 退订命令读完每个参数对应的确认后返回。`quiet` 为 true 时不打印任何内容。
+fn subscribe(rc: &RedisCommand, config: &Config, conn: &mut Connection, quiet: bool) -> Result<(), RedisError> {
+    conn.send(&rc.encode(config.protocol))?;
+    let streaming = matches!(rc.name, "SUBSCRIBE" | "PSUBSCRIBE");
+    let _interrupt = if streaming { Some(interrupt_on_ctrl_c(conn)?) } else { None };
+    if streaming {
+        conn.set_read_timeout(None)?;
+        if !quiet && config.output == OutputFormat::Tty {
+            println!("Reading messages... (press Ctrl-C to quit)");
+        }
     }
 
-    for i in 0..count {
-        print!("{}: ", i);
-        read_reply(reader, quiet)?;
+    // 每个参数对应一条确认，不带参数的退订只有一条
+    let mut pending = rc.argv.len().max(1);
+    let mut out = io::stdout();
+    loop {
+        let reply = match conn.read_reply() {
+            Ok(reply) => reply,
+            Err(_) if interrupted() => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        if !quiet {
+            let _ = out.write_all(&format_output(&reply, config.output));
+            let _ = out.flush();
+        }
+
+        match PubSubMessage::from_reply(&reply) {
+            Some(PubSubMessage::Subscription { count, .. }) => {
+                conn.streaming = count > 0;
+                pending = pending.saturating_sub(1);
+                if pending == 0 && (!streaming || count == 0) {
+                    return Ok(());
+                }
+            }
+            Some(_) => {}
+            None => {
+                return match reply {
+                    RedisValue::Error(err) => Err(RedisError::Server(err)),
+                    other => Err(RedisError::Protocol(format!("unexpected reply in pub/sub mode: {:?}", other))),
+                };
+            }
+        }
     }
-    Ok(())
 }
 
-/// 读取一行字符串，去掉 \r\n
-fn read_line(reader: &mut BufReader<TcpStream>) -> Result<String, String> {
-    let mut buf = String::new();
-    reader.read_line(&mut buf).map_err(|e| e.to_string())?;
-    Ok(buf.trim_end_matches("\r\n").to_string())
+// ===================== pipe 模式 =====================
+
+/// pipe 模式的统计结果
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PipeStats {
+    pub replies: u64,
+    pub errors: u64,
 }
 
+/// 将 `input` 中的命令流式写入服务端，同时由另一个线程读取回复。
+///
+/// 输入以 `*` 开头时视为原始 RESP 原样转发，否则按行切分并用 `to_resp` 编码。
+/// 所有数据写完后追加一条 `ECHO <magic>`，读线程读到该回复即说明前面的回复已全部收到。
+pub fn pipe_mode<R: BufRead>(conn: Connection, mut input: R) -> Result<PipeStats, RedisError> {
+    let (mut reader, mut writer) = conn.into_split();
+    let magic = pipe_magic();
 
-// ===================== 命令表 =====================
-// arity 正数表示参数个数，负数表示最少参数个数
-pub static CMD_TABLE: &[RedisCommand] = &[
-    RedisCommand { name: "AUTH", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "GET", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "SET", arity: 3, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "SETNX", arity: 3, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "APPEND", arity: 3, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "SUBSTR", arity: 4, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "DEL", arity: -2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "EXISTS", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "INCR", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "DECR", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "RPUSH", arity: 3, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "LPUSH", arity: 3, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "RPOP", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "LPOP", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "BRPOP", arity: -3, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "BLPOP", arity: -3, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "LLEN", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "LINDEX", arity: 3, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "LSET", arity: 4, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "LRANGE", arity: 4, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "LTRIM", arity: 4, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "LREM", arity: 4, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "RPOPLPUSH", arity: 3, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "SADD", arity: 3, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "SREM", arity: 3, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "SMOVE", arity: 4, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "SISMEMBER", arity: 3, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "SCARD", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "SPOP", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "SRANDMEMBER", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "SINTER", arity: -2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "SINTERSTORE", arity: -3, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "SUNION", arity: -2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "SUNIONSTORE", arity: -3, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "SDIFF", arity: -2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "SDIFFSTORE", arity: -3, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "SMEMBERS", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "ZADD", arity: 4, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "ZINCRBY", arity: 4, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "ZREM", arity: 3, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "ZREMRANGEBYSCORE", arity: 4, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "ZRANGE", arity: -4, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "ZRANK", arity: 3, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "ZREVRANK", arity: 3, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "ZRANGEBYSCORE", arity: -4, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "ZCOUNT", arity: 4, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "ZREVRANGE", arity: -4, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "ZCARD", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "ZSCORE", arity: 3, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "INCRBY", arity: 3, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "DECRBY", arity: 3, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "GETSET", arity: 3, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "RANDOMKEY", arity: 1, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "SELECT", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "MOVE", arity: 3, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "RENAME", arity: 3, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "RENAMENX", arity: 3, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "KEYS", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "DBSIZE", arity: 1, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "PING", arity: 1, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "ECHO", arity: 2, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "SAVE", arity: 1, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "BGSAVE", arity: 1, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "BGREWRITEAOF", arity: 1, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "SHUTDOWN", arity: 1, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "LASTSAVE", arity: 1, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "TYPE", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "FLUSHDB", arity: 1, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "FLUSHALL", arity: 1, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "SORT", arity: -2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "INFO", arity: 1, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "MGET", arity: -2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "EXPIRE", arity: 3, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "EXPIREAT", arity: 3, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "TTL", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "SLAVEOF", arity: 3, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "DEBUG", arity: -2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "MSET", arity: -3, flags: CommandType::MultiBulk, argv: vec![] },
-    RedisCommand { name: "MSETNX", arity: -3, flags: CommandType::MultiBulk, argv: vec![] },
-    RedisCommand { name: "MONITOR", arity: 1, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "MULTI", arity: 1, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "EXEC", arity: 1, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "DISCARD", arity: 1, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "HSET", arity: 4, flags: CommandType::MultiBulk, argv: vec![] },
-    RedisCommand { name: "HGET", arity: 3, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "HDEL", arity: 3, flags: CommandType::Bulk, argv: vec![] },
-    RedisCommand { name: "HLEN", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "HKEYS", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "HVALS", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "HGETALL", arity: 2, flags: CommandType::Inline, argv: vec![] },
-    RedisCommand { name: "HEXISTS", arity: 3, flags: CommandType::Bulk, argv: vec![] },
-];
-
-pub fn repl(config: &mut Config, stream: &mut TcpStream) {
-    let stdin = io::stdin();
-    let mut handle = stdin.lock();
-    let mut line = String::new();
+    let expected = magic.clone();
+    let reader_thread = thread::spawn(move || -> Result<PipeStats, RedisError> {
+        let mut stats = PipeStats::default();
+        loop {
+            match read_reply(&mut reader)? {
+                RedisValue::Bulk(b) if b == expected => return Ok(stats),
+                RedisValue::Error(err) => {
+                    eprintln!("{}", err);
+                    stats.errors += 1;
+                }
+                _ => {}
+            }
+            stats.replies += 1;
+        }
+    });
 
-    loop {
-        print!("r-cli> ");
-        io::stdout().flush().unwrap();
+    let raw = input.fill_buf()?.first() == Some(&b'*');
+    if raw {
+        io::copy(&mut input, &mut writer)?;
+    } else {
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if input.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            let args = match split_args(&line) {
+                Ok(args) if args.is_empty() => continue,
+                Ok(args) => args,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    continue;
+                }
+            };
+            match RedisCommand::build(&args) {
+                Ok(cmd) => writer.write_all(&cmd.to_resp())?,
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+    }
 
-        line.clear();
-        if handle.read_line(&mut line).unwrap_or(0) == 0 { break; }
-        let args: Vec<&str> = line.trim().split_whitespace().collect();
-        if args.is_empty() { continue; }
-        if ["quit", "exit"].contains(&args[0].to_ascii_lowercase().as_str()) { break; }
+    let echo = RedisCommand::build(&[b"ECHO".as_slice(), &magic])?;
+    writer.write_all(&echo.to_resp())?;
+    writer.flush()?;
+    eprintln!("All data transferred. Waiting for the last reply...");
+
+    reader_thread
+        .join()
+        .map_err(|_| RedisError::Io(io::Error::other("pipe reader thread panicked")))?
+}
+
+/// 生成 20 字节的结束标记
+fn pipe_magic() -> Vec<u8> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    format!("{:020x}", nanos ^ ((std::process::id() as u128) << 64)).as_bytes()[..20].to_vec()
+}
+
+// ===================== RESP 解析 =====================
+
+/// Redis 回复值
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedisValue {
+    /// 状态回复 `+OK`
+    Status(String),
+    /// 错误回复 `-ERR ...`
+    Error(String),
+    /// 整数回复 `:1`
+    Integer(i64),
+    /// bulk 回复 `$3\r\nfoo`，保留原始字节
+    Bulk(Vec<u8>),
+    /// `$-1` 或 `*-1`
+    Nil,
+    /// multi-bulk 回复，元素可以嵌套
+    Array(Vec<RedisValue>),
+}
 
-        cli_send_command(args, config, stream, false);
+/// 核心读取函数，递归处理所有 Redis 回复类型
+pub fn read_reply<R: BufRead>(reader: &mut R) -> Result<RedisValue, RedisError> {
+    let mut first_byte = [0u8; 1];
+    let n = loop {
+        match reader.read(&mut first_byte) {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            result => break result?,
+        }
+    };
+    if n == 0 {
+        return Err(RedisError::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by server")));
     }
+
+    // 已经读到类型字节后再遇到 EOF 说明回复被截断，不算正常关闭
+    read_reply_body(first_byte[0], reader).map_err(|err| match err {
+        RedisError::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+            RedisError::Protocol("connection closed in the middle of a reply".to_string())
+        }
+        other => other,
+    })
 }
 
-pub fn init(config: &mut Config, stream: &mut TcpStream) {
-    if let Some(auth) = &config.auth {
-        cli_send_command(vec!["AUTH", &auth], &config, stream, true);
+fn read_reply_body<R: BufRead>(type_byte: u8, reader: &mut R) -> Result<RedisValue, RedisError> {
+    match type_byte as char {
+        '+' => Ok(RedisValue::Status(cli_read_single_line_reply(reader)?)),
+        '-' => Ok(RedisValue::Error(cli_read_single_line_reply(reader)?)),
+        ':' => {
+            let line = cli_read_single_line_reply(reader)?;
+            let n = line.parse().map_err(|_| RedisError::Protocol(format!("invalid integer reply: {}", line)))?;
+            Ok(RedisValue::Integer(n))
+        }
+        '$' => cli_read_bulk_reply(reader),
+        '*' => cli_read_multi_bulk_reply(reader),
+        other => Err(RedisError::Protocol(format!("got '{}' as reply type byte", other))),
+    }
+}
+
+/// 读取单行回复（+、-、:）
+fn cli_read_single_line_reply<R: BufRead>(reader: &mut R) -> Result<String, RedisError> {
+    read_line(reader)
+}
+
+/// 读取 bulk 回复 ($)
+fn cli_read_bulk_reply<R: BufRead>(reader: &mut R) -> Result<RedisValue, RedisError> {
+    let len_line = read_line(reader)?;
+    let bulklen: isize = len_line.parse().map_err(|_| RedisError::Protocol(format!("invalid bulk length: {}", len_line)))?;
+    if bulklen < 0 {
+        return Ok(RedisValue::Nil);
+    }
+
+    let mut buf = vec![0u8; bulklen as usize + 2]; // +2 for \r\n
+    reader.read_exact(&mut buf)?;
+    buf.truncate(bulklen as usize);
+    Ok(RedisValue::Bulk(buf))
+}
+
+/// 读取 multi-bulk 回复 (*)
+fn cli_read_multi_bulk_reply<R: BufRead>(reader: &mut R) -> Result<RedisValue, RedisError> {
+    let len_line = read_line(reader)?;
+    let count: isize = len_line
+        .parse()
+        .map_err(|_| RedisError::Protocol(format!("invalid multi-bulk count: {}", len_line)))?;
+    if count < 0 {
+        return Ok(RedisValue::Nil);
+    }
+
+    let mut items = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        items.push(read_reply(reader)?);
+    }
+    Ok(RedisValue::Array(items))
+}
+
+/// 读取一行字符串，去掉 \r\n
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, RedisError> {
+    let mut buf = Vec::new();
+    if reader.read_until(b'\n', &mut buf)? == 0 {
+        return Err(RedisError::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by server")));
+    }
+    if !buf.ends_with(b"\r\n") {
+        return Err(RedisError::Protocol("reply line is not terminated by CRLF".to_string()));
+    }
+    buf.truncate(buf.len() - 2);
+    String::from_utf8(buf).map_err(|_| RedisError::Protocol("reply line is not valid UTF-8".to_string()))
+}
+
+// ===================== 回复格式化 =====================
+
+/// 仿照 sdscatrepr：加双引号，转义引号、反斜杠和控制字符，其余不可打印字节输出为 `\xNN`
+pub fn repr_bytes(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() + 2);
+    s.push('"');
+    for &b in bytes {
+        match b {
+            b'\\' => s.push_str("\\\\"),
+            b'"' => s.push_str("\\\""),
+            b'\n' => s.push_str("\\n"),
+            b'\r' => s.push_str("\\r"),
+            b'\t' => s.push_str("\\t"),
+            0x07 => s.push_str("\\a"),
+            0x08 => s.push_str("\\b"),
+            0x20..=0x7e => s.push(b as char),
+            _ => s.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    s.push('"');
+    s
+}
+
+/// 将回复格式化为终端输出，每个值以换行结尾
+pub fn format_reply(reply: &RedisValue) -> String {
+    format_reply_tty(reply, "")
+}
+
+/// 仿照 cliFormatReplyTTY：数组元素编号为 `1) 2) 3)`，嵌套数组按上层编号的宽度缩进
+fn format_reply_tty(reply: &RedisValue, prefix: &str) -> String {
+    match reply {
+        RedisValue::Status(s) => format!("{}\n", s),
+        RedisValue::Error(e) => format!("(error) {}\n", e),
+        RedisValue::Integer(n) => format!("(integer) {}\n", n),
+        RedisValue::Bulk(b) => format!("{}\n", repr_bytes(b)),
+        RedisValue::Nil => "(nil)\n".to_string(),
+        RedisValue::Array(items) if items.is_empty() => "(empty list or set)\n".to_string(),
+        RedisValue::Array(items) => {
+            let idxlen = items.len().to_string().len();
+            let child_prefix = format!("{}{}", prefix, " ".repeat(idxlen + 2));
+            let mut s = String::new();
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    s += prefix;
+                }
+                s += &format!("{:>width$}) ", i + 1, width = idxlen);
+                s += &format_reply_tty(item, &child_prefix);
+            }
+            s
+        }
+    }
+}
+
+/// EXEC 的回复与入队的命令逐条配对显示：
+///
+/// ```text
+/// 1) SET k v
+///    OK
+/// 2) INCR counter
+///    (integer) 3
+/// ```
+fn format_exec_reply(queued: &[QueuedCommand], reply: &RedisValue) -> String {
+    let items = match reply {
+        RedisValue::Array(items) if items.len() == queued.len() && !items.is_empty() => items,
+        _ => return format_reply(reply),
+    };
+    let idxlen = items.len().to_string().len();
+    let prefix = " ".repeat(idxlen + 2);
+    let mut s = String::new();
+    for (i, (cmd, item)) in queued.iter().zip(items).enumerate() {
+        s += &format!("{:>width$}) {}\n", i + 1, cmd.display, width = idxlen);
+        s += &prefix;
+        s += &format_reply_tty(item, &prefix);
+    }
+    s
+}
+
+/// 按指定格式输出回复，结果以换行结尾
+pub fn format_output(reply: &RedisValue, format: OutputFormat) -> Vec<u8> {
+    let mut out = match format {
+        OutputFormat::Tty => return format_reply(reply).into_bytes(),
+        OutputFormat::Raw => format_reply_raw(reply),
+        OutputFormat::Csv => format_reply_csv(reply).into_bytes(),
+        OutputFormat::Json => format_reply_json(reply).into_bytes(),
+    };
+    out.push(b'\n');
+    out
+}
+
+/// 仿照 cliFormatReplyRaw：原样输出内容，数组元素各占一行，nil 输出为空
+fn format_reply_raw(reply: &RedisValue) -> Vec<u8> {
+    match reply {
+        RedisValue::Status(s) | RedisValue::Error(s) => s.as_bytes().to_vec(),
+        RedisValue::Integer(n) => n.to_string().into_bytes(),
+        RedisValue::Bulk(b) => b.clone(),
+        RedisValue::Nil => Vec::new(),
+        RedisValue::Array(items) => items
+            .iter()
+            .map(format_reply_raw)
+            .collect::<Vec<_>>()
+            .join(&b'\n'),
+    }
+}
+
+/// 仿照 cliFormatReplyCSV：字符串加引号转义，数组元素以逗号连接
+fn format_reply_csv(reply: &RedisValue) -> String {
+    match reply {
+        RedisValue::Status(s) => repr_bytes(s.as_bytes()),
+        RedisValue::Error(e) => format!("ERROR,{}", repr_bytes(e.as_bytes())),
+        RedisValue::Integer(n) => n.to_string(),
+        RedisValue::Bulk(b) => repr_bytes(b),
+        RedisValue::Nil => "NULL".to_string(),
+        RedisValue::Array(items) => items.iter().map(format_reply_csv).collect::<Vec<_>>().join(","),
+    }
+}
+
+/// 输出为 JSON：nil 为 `null`，数组为 JSON 数组，错误为 `{"error": "..."}`
+fn format_reply_json(reply: &RedisValue) -> String {
+    match reply {
+        RedisValue::Status(s) => json_string(s.as_bytes()),
+        RedisValue::Error(e) => format!("{{\"error\":{}}}", json_string(e.as_bytes())),
+        RedisValue::Integer(n) => n.to_string(),
+        RedisValue::Bulk(b) => json_string(b),
+        RedisValue::Nil => "null".to_string(),
+        RedisValue::Array(items) => {
+            format!("[{}]", items.iter().map(format_reply_json).collect::<Vec<_>>().join(","))
+        }
+    }
+}
+
+/// JSON 字符串转义。不是合法 UTF-8 的内容按字节输出为 `\u00NN`
+fn json_string(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() + 2);
+    s.push('"');
+    match std::str::from_utf8(bytes) {
+        Ok(text) => {
+            for c in text.chars() {
+                match c {
+                    '"' => s.push_str("\\\""),
+                    '\\' => s.push_str("\\\\"),
+                    '\n' => s.push_str("\\n"),
+                    '\r' => s.push_str("\\r"),
+                    '\t' => s.push_str("\\t"),
+                    c if (c as u32) < 0x20 => s.push_str(&format!("\\u{:04x}", c as u32)),
+                    c => s.push(c),
+                }
+            }
+        }
+        Err(_) => {
+            for &b in bytes {
+                match b {
+                    b'"' => s.push_str("\\\""),
+                    b'\\' => s.push_str("\\\\"),
+                    0x20..=0x7e => s.push(b as char),
+                    _ => s.push_str(&format!("\\u{:04x}", b)),
+                }
+            }
+        }
+    }
+    s.push('"');
+    s
+}
+
+// ===================== 命令表 =====================
+
+/// 命令表的唯一来源：`NAME => method(参数), arity, 协议类型;`
+///
+/// arity 正数表示参数个数，负数表示最少参数个数。参数列表中 `;` 之后的为可变参数，
+/// 如 `; keys, timeout` 表示若干个 key 后跟一个 timeout。
+/// `$callback` 会收到完整的表，`CMD_TABLE` 和 `Client` 的命令方法都由它生成。
+/// `monitor` 组的命令执行后连接只接收推送，不生成 `Client` 方法；
+/// `pubsub` 组的命令回复以推送消息的形式到达，方法生成在 `Subscriber` 上。
+macro_rules! redis_commands {
+    ($callback:ident) => {
+        $callback! {
+            commands {
+                AUTH => auth(password),                                  2, Inline;
+                GET => get(key),                                         2, Inline;
+                SET => set(key, value),                                  3, Bulk;
+                SETNX => setnx(key, value),                              3, Bulk;
+                APPEND => append(key, value),                            3, Bulk;
+                SUBSTR => substr(key, start, end),                       4, Inline;
+                DEL => del(; keys),                                      -2, Inline;
+                EXISTS => exists(key),                                   2, Inline;
+                INCR => incr(key),                                       2, Inline;
+                DECR => decr(key),                                       2, Inline;
+                RPUSH => rpush(key, value),                              3, Bulk;
+                LPUSH => lpush(key, value),                              3, Bulk;
+                RPOP => rpop(key),                                       2, Inline;
+                LPOP => lpop(key),                                       2, Inline;
+                BRPOP => brpop(; keys, timeout),                         -3, Inline;
+                BLPOP => blpop(; keys, timeout),                         -3, Inline;
+                LLEN => llen(key),                                       2, Inline;
+                LINDEX => lindex(key, index),                            3, Inline;
+                LSET => lset(key, index, value),                         4, Bulk;
+                LRANGE => lrange(key, start, stop),                      4, Inline;
+                LTRIM => ltrim(key, start, stop),                        4, Inline;
+                LREM => lrem(key, count, value),                         4, Bulk;
+                RPOPLPUSH => rpoplpush(source, destination),             3, Bulk;
+                SADD => sadd(key, member),                               3, Bulk;
+                SREM => srem(key, member),                               3, Bulk;
+                SMOVE => smove(source, destination, member),             4, Bulk;
+                SISMEMBER => sismember(key, member),                     3, Bulk;
+                SCARD => scard(key),                                     2, Inline;
+                SPOP => spop(key),                                       2, Inline;
+                SRANDMEMBER => srandmember(key),                         2, Inline;
+                SINTER => sinter(; keys),                                -2, Inline;
+                SINTERSTORE => sinterstore(destination; keys),           -3, Inline;
+                SUNION => sunion(; keys),                                -2, Inline;
+                SUNIONSTORE => sunionstore(destination; keys),           -3, Inline;
+                SDIFF => sdiff(; keys),                                  -2, Inline;
+                SDIFFSTORE => sdiffstore(destination; keys),             -3, Inline;
+                SMEMBERS => smembers(key),                               2, Inline;
+                ZADD => zadd(key, score, member),                        4, Bulk;
+                ZINCRBY => zincrby(key, increment, member),              4, Bulk;
+                ZREM => zrem(key, member),                               3, Bulk;
+                ZREMRANGEBYSCORE => zremrangebyscore(key, min, max),     4, Inline;
+                ZRANGE => zrange(key, start, stop; options),             -4, Inline;
+                ZRANK => zrank(key, member),                             3, Bulk;
+                ZREVRANK => zrevrank(key, member),                       3, Bulk;
+                ZRANGEBYSCORE => zrangebyscore(key, min, max; options),  -4, Inline;
+                ZCOUNT => zcount(key, min, max),                         4, Inline;
+                ZREVRANGE => zrevrange(key, start, stop; options),       -4, Inline;
+                ZCARD => zcard(key),                                     2, Inline;
+                ZSCORE => zscore(key, member),                           3, Bulk;
+                INCRBY => incrby(key, increment),                        3, Inline;
+                DECRBY => decrby(key, decrement),                        3, Inline;
+                GETSET => getset(key, value),                            3, Bulk;
+                RANDOMKEY => randomkey(),                                1, Inline;
+                SELECT => select(index),                                 2, Inline;
+                MOVE => move_key(key, db),                               3, Inline;
+                RENAME => rename(key, newkey),                           3, Inline;
+                RENAMENX => renamenx(key, newkey),                       3, Inline;
+                KEYS => keys(pattern),                                   2, Inline;
+                DBSIZE => dbsize(),                                      1, Inline;
+                PING => ping(),                                          1, Inline;
+                ECHO => echo(message),                                   2, Bulk;
+                SAVE => save(),                                          1, Inline;
+                BGSAVE => bgsave(),                                      1, Inline;
+                BGREWRITEAOF => bgrewriteaof(),                          1, Inline;
+                SHUTDOWN => shutdown(),                                  1, Inline;
+                LASTSAVE => lastsave(),                                  1, Inline;
+                TYPE => key_type(key),                                   2, Inline;
+                FLUSHDB => flushdb(),                                    1, Inline;
+                FLUSHALL => flushall(),                                  1, Inline;
+                SORT => sort(key; options),                              -2, Inline;
+                INFO => info(),                                          1, Inline;
+                MGET => mget(; keys),                                    -2, Inline;
+                EXPIRE => expire(key, seconds),                          3, Inline;
+                EXPIREAT => expireat(key, timestamp),                    3, Inline;
+                TTL => ttl(key),                                         2, Inline;
+                SLAVEOF => slaveof(host, port),                          3, Inline;
+                DEBUG => debug(subcommand; args),                        -2, Inline;
+                MSET => mset(; key_values),                              -3, MultiBulk;
+                MSETNX => msetnx(; key_values),                          -3, MultiBulk;
+                MULTI => multi(),                                        1, Inline;
+                EXEC => exec(),                                          1, Inline;
+                DISCARD => discard(),                                    1, Inline;
+                HSET => hset(key, field, value),                         4, MultiBulk;
+                HGET => hget(key, field),                                3, Bulk;
+                HDEL => hdel(key, field),                                3, Bulk;
+                HLEN => hlen(key),                                       2, Inline;
+                HKEYS => hkeys(key),                                     2, Inline;
+                HVALS => hvals(key),                                     2, Inline;
+                HGETALL => hgetall(key),                                 2, Inline;
+                HEXISTS => hexists(key, field),                          3, Bulk;
+                PUBLISH => publish(channel, message),                    3, Bulk;
+            }
+            monitor {
+                MONITOR => monitor(),                                    1, Inline;
+            }
+            pubsub {
+                SUBSCRIBE => subscribe(; channels),                      -2, Inline;
+                UNSUBSCRIBE => unsubscribe(; channels),                  -1, Inline;
+                PSUBSCRIBE => psubscribe(; patterns),                    -2, Inline;
+                PUNSUBSCRIBE => punsubscribe(; patterns),                -1, Inline;
+            }
+        }
+    };
+}
+pub(crate) use redis_commands;
+
+macro_rules! cmd_table {
+    (
+        commands { $($name:ident => $method:ident $params:tt, $arity:expr, $flags:ident;)* }
+        monitor { $($mname:ident => $mmethod:ident $mparams:tt, $marity:expr, $mflags:ident;)* }
+        pubsub { $($pname:ident => $pmethod:ident $pparams:tt, $parity:expr, $pflags:ident;)* }
+    ) => {
+        pub static CMD_TABLE: &[RedisCommand] = &[
+            $(cmd_table!(@entry $name $params, $arity, $flags),)*
+            $(cmd_table!(@entry $mname $mparams, $marity, $mflags),)*
+            $(cmd_table!(@entry $pname $pparams, $parity, $pflags),)*
+        ];
+    };
+    (@entry $name:ident ($($arg:ident),* $(; $rest:ident $(, $tail:ident)*)?), $arity:expr, $flags:ident) => {
+        RedisCommand {
+            name: stringify!($name),
+            arity: $arity,
+            flags: CommandType::$flags,
+            params: &[$(stringify!($arg)),*],
+            variadic: &[$(stringify!($rest) $(, stringify!($tail))*)?],
+            argv: vec![],
+        }
+    };
+}
+
+redis_commands!(cmd_table);
+
+// ===================== 参数解析 =====================
+
+/// 仿照 sdssplitargs 切分一行命令。
+///
+/// 双引号内支持 `\n`、`\r`、`\t`、`\b`、`\a` 和 `\xNN` 转义，单引号内只支持 `\'`。
+/// 引号未闭合，或闭合引号后紧跟非空白字符时返回错误。
+pub fn split_args(line: &[u8]) -> Result<Vec<Vec<u8>>, RedisError> {
+    let mut args = Vec::new();
+    let mut i = 0;
+
+    loop {
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i >= line.len() {
+            return Ok(args);
+        }
+
+        let mut current = Vec::new();
+        let mut in_dq = false;
+        let mut in_sq = false;
+        loop {
+            if in_dq {
+                match line.get(i) {
+                    None => return Err(RedisError::Command("Invalid argument(s): unbalanced double quotes".to_string())),
+                    Some(b'\\') if i + 3 < line.len()
+                        && line[i + 1] == b'x'
+                        && line[i + 2].is_ascii_hexdigit()
+                        && line[i + 3].is_ascii_hexdigit() =>
+                    {
+                        let hex = std::str::from_utf8(&line[i + 2..i + 4]).unwrap();
+                        current.push(u8::from_str_radix(hex, 16).unwrap());
+                        i += 3;
+                    }
+                    Some(b'\\') if i + 1 < line.len() => {
+                        i += 1;
+                        current.push(match line[i] {
+                            b'n' => b'\n',
+                            b'r' => b'\r',
+                            b't' => b'\t',
+                            b'b' => 0x08,
+                            b'a' => 0x07,
+                            other => other,
+                        });
+                    }
+                    Some(b'"') => {
+                        if i + 1 < line.len() && !line[i + 1].is_ascii_whitespace() {
+                            return Err(RedisError::Command(
+                                "Invalid argument(s): closing quote must be followed by a space".to_string(),
+                            ));
+                        }
+                        i += 1;
+                        break;
+                    }
+                    Some(&c) => current.push(c),
+                }
+            } else if in_sq {
+                match line.get(i) {
+                    None => return Err(RedisError::Command("Invalid argument(s): unbalanced single quotes".to_string())),
+                    Some(b'\\') if line.get(i + 1) == Some(&b'\'') => {
+                        current.push(b'\'');
+                        i += 1;
+                    }
+                    Some(b'\'') => {
+                        if i + 1 < line.len() && !line[i + 1].is_ascii_whitespace() {
+                            return Err(RedisError::Command(
+                                "Invalid argument(s): closing quote must be followed by a space".to_string(),
+                            ));
+                        }
+                        i += 1;
+                        break;
+                    }
+                    Some(&c) => current.push(c),
+                }
+            } else {
+                match line.get(i) {
+                    None => break,
+                    Some(c) if c.is_ascii_whitespace() => break,
+                    Some(b'"') => in_dq = true,
+                    Some(b'\'') => in_sq = true,
+                    Some(&c) => current.push(c),
+                }
+            }
+            i += 1;
+        }
+        args.push(current);
+    }
+}
+
+// ===================== REPL =====================
+
+/// 保存在用户主目录下的历史文件
+const HISTORY_FILE: &str = ".rcli_history";
+
+/// `LineReader::read_line` 的结果
+enum Input {
+    Line(Vec<u8>),
+    /// 编辑时按下了 Ctrl-C
+    Interrupted,
+    /// 标准输入结束（Ctrl-D）
+    Eof,
+}
+
+/// REPL 的输入：终端上使用带行编辑与持久化历史的编辑器，否则逐行读取标准输入
+enum LineReader {
+    Editor { editor: Box<Editor<ReplHelper, FileHistory>>, history: Option<PathBuf> },
+    Plain { stdin: StdinLock<'static>, tty: bool },
+}
+
+impl LineReader {
+    fn new() -> Self {
+        let stdin = io::stdin();
+        let tty = stdin.is_terminal();
+        if tty && let Ok(mut editor) = Editor::new() {
+            editor.set_helper(Some(ReplHelper));
+            let history = env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+            if let Some(path) = &history {
+                let _ = editor.load_history(path);
+            }
+            return LineReader::Editor { editor: Box::new(editor), history };
+        }
+        LineReader::Plain { stdin: stdin.lock(), tty }
+    }
+
+    /// 读取一行，不含行尾换行符
+    fn read_line(&mut self, prompt: &str) -> Input {
+        match self {
+            LineReader::Editor { editor, .. } => match editor.readline(prompt) {
+                Ok(line) => Input::Line(line.into_bytes()),
+                Err(ReadlineError::Interrupted) => Input::Interrupted,
+                Err(_) => Input::Eof,
+            },
+            LineReader::Plain { stdin, tty } => {
+                if *tty {
+                    print!("{}", prompt);
+                    let _ = io::stdout().flush();
+                }
+                let mut line = Vec::new();
+                if stdin.read_until(b'\n', &mut line).unwrap_or(0) == 0 {
+                    return Input::Eof;
+                }
+                if line.ends_with(b"\n") {
+                    line.pop();
+                }
+                Input::Line(line)
+            }
+        }
+    }
+
+    /// 读取一条完整的命令：引号未闭合时继续读取下一行，换行作为参数内容的一部分。
+    /// 输入结束时返回 `None`；Ctrl-C 放弃整条命令，包括已输入的续行，返回空命令
+    fn read_command(&mut self, prompt: &str) -> Option<Vec<u8>> {
+        let mut line = match self.read_line(prompt) {
+            Input::Line(line) => line,
+            Input::Interrupted => return Some(Vec::new()),
+            Input::Eof => return None,
+        };
+        while has_open_quote(&line) {
+            match self.read_line("... ") {
+                Input::Line(more) => {
+                    line.push(b'\n');
+                    line.extend(more);
+                }
+                Input::Interrupted => return Some(Vec::new()),
+                Input::Eof => break,
+            }
+        }
+        if let LineReader::Editor { editor, .. } = self
+            && !line.iter().all(u8::is_ascii_whitespace)
+        {
+            let _ = editor.add_history_entry(history_entry(&line));
+            // 与 redis-cli 一样每条命令后立即写入，阻塞命令中按 Ctrl-C 或关闭终端也不会丢失历史
+            self.save_history();
+        }
+        Some(line)
+    }
+
+    fn save_history(&mut self) {
+        if let LineReader::Editor { editor, history: Some(path) } = self {
+            let _ = editor.save_history(path);
+        }
+    }
+}
+
+/// 行编辑器的补全与提示：命令名按命令表补全，输入命令名后以暗色提示其余参数
+struct ReplHelper;
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete_command(&line[..pos]))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<String> {
+        if pos < line.len() {
+            return None;
+        }
+        command_hint(line)
+    }
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("\x1b[2m{}\x1b[0m", hint))
+    }
+}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+/// 补全行首的命令名，或 `help` 之后的帮助主题，不区分大小写；输入是小写时候选也用小写。
+/// 返回替换的起始位置与候选
+fn complete_command(prefix: &str) -> (usize, Vec<String>) {
+    let start = prefix.len() - prefix.trim_start().len();
+    let word = &prefix[start..];
+    let (start, word, names): (usize, &str, Vec<String>) = match word.split_once(char::is_whitespace) {
+        None => (start, word, CMD_TABLE.iter().map(|c| c.name.to_string()).chain(["HELP".to_string()]).collect()),
+        Some((cmd, topic)) if cmd.eq_ignore_ascii_case("help") => {
+            let topic = topic.trim_start();
+            if topic.contains(char::is_whitespace) {
+                return (prefix.len(), Vec::new());
+            }
+            (prefix.len() - topic.len(), topic, help_topics().collect())
+        }
+        Some(_) => return (prefix.len(), Vec::new()),
+    };
+    let lowercase = word.starts_with(|c: char| c.is_ascii_lowercase());
+    let candidates = names
+        .into_iter()
+        .filter(|name| name.len() >= word.len() && name.as_bytes()[..word.len()].eq_ignore_ascii_case(word.as_bytes()))
+        .map(|name| if lowercase { name.to_ascii_lowercase() } else { name })
+        .collect();
+    (start, candidates)
+}
+
+/// 光标在行尾时提示尚未输入的参数：刚输入完命令名时提示全部参数，
+/// 之后每输入一个参数去掉一项，到可重复的参数为止。正在输入某个参数时不提示
+fn command_hint(line: &str) -> Option<String> {
+    let args = split_args(line.as_bytes()).ok()?;
+    let name = args.first()?;
+    let cmd = CMD_TABLE.iter().find(|c| c.name.as_bytes().eq_ignore_ascii_case(name))?;
+    let hints = cmd.arg_hints();
+    let (typed, sep) = match (args.len(), line.ends_with(char::is_whitespace)) {
+        (n, true) => (n - 1, ""),
+        (1, false) => (0, " "),
+        _ => return None,
+    };
+    let repeated = hints.iter().position(|h| h.starts_with('[')).unwrap_or(hints.len());
+    let rest = &hints[typed.min(repeated)..];
+    (!rest.is_empty()).then(|| format!("{}{}", sep, rest.join(" ")))
+}
+
+/// 提示符，如 `127.0.0.1:6379[3]> `；0 号数据库不显示编号，事务中附加已入队的命令数
+fn prompt(config: &Config, queued: Option<&[QueuedCommand]>) -> String {
+    let mut prompt = config.server_addr();
+    if config.dbnum != 0 {
+        prompt += &format!("[{}]", config.dbnum);
+    }
+    if let Some(queued) = queued {
+        prompt += &format!("(TX {})", queued.len());
+    }
+    prompt + "> "
+}
+
+/// 按 `split_args` 的规则扫描，判断行尾是否仍处于引号之中。
+///
+/// 双引号内反斜杠转义其后任意字符，单引号内只有 `\'` 是转义
+fn has_open_quote(line: &[u8]) -> bool {
+    let mut quote = None;
+    let mut i = 0;
+    while i < line.len() {
+        match (quote, line[i]) {
+            (None, q @ (b'"' | b'\'')) => quote = Some(q),
+            (Some(b'"'), b'\\') if i + 1 < line.len() => i += 1,
+            (Some(b'\''), b'\\') if line.get(i + 1) == Some(&b'\'') => i += 1,
+            (Some(q), c) if c == q => quote = None,
+            _ => {}
+        }
+        i += 1;
+    }
+    quote.is_some()
+}
+
+/// 写入历史的内容。AUTH 的参数是密码，替换掉以免明文保存在历史文件中。
+///
+/// 只看第一个以空白分隔的词，引号未闭合等无法解析的行同样会被替换
+fn history_entry(line: &[u8]) -> String {
+    let name = line.split(u8::is_ascii_whitespace).find(|word| !word.is_empty()).unwrap_or_default();
+    let unquoted: Vec<u8> = name.iter().copied().filter(|&b| b != b'"' && b != b'\'').collect();
+    if unquoted.eq_ignore_ascii_case(b"auth") {
+        format!("{} <redacted>", String::from_utf8_lossy(name))
+    } else {
+        String::from_utf8_lossy(line).into_owned()
+    }
+}
+
+/// 交互式循环。标准输入是终端时支持行编辑与历史（保存在 `~/.rcli_history`）；
+/// 否则不打印提示符，可以直接 `cat cmds.txt | r-cli` 批量执行
+pub fn repl(config: &mut Config, conn: &mut Connection) {
+    let mut reader = LineReader::new();
+
+    while let Some(line) = reader.read_command(&prompt(config, conn.queued())) {
+        let args = match split_args(&line) {
+            Ok(args) => args,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+        if args.is_empty() { continue; }
+        if args[0].eq_ignore_ascii_case(b"quit") || args[0].eq_ignore_ascii_case(b"exit") { break; }
+
+        cli_send_command(&args, config, conn, false);
+        // MONITOR、SUBSCRIBE 结束后连接仍处于推送状态，不能再发送普通命令
+        if conn.is_streaming() { break; }
+    }
+}
+
+/// 按 `config` 连接服务端并设置超时，不执行 AUTH 与 SELECT
+pub fn connect(config: &Config) -> Result<Connection, RedisError> {
+    let stream: Stream = match &config.hostsocket {
+        Some(path) => unix_connect(path)?.into(),
+        None => {
+            let stream = tcp_connect(&config.hostip, config.hostport, config.connect_timeout, config.address_family)?;
+            tcp_no_delay(&stream, config.tcp_nodelay)?;
+            if let Some(interval) = config.keepalive {
+                tcp_keep_alive(&stream, interval)?;
+            }
+            if let Some(size) = config.send_buffer {
+                set_send_buffer(&stream, size)?;
+            }
+            if let Some(size) = config.recv_buffer {
+                set_recv_buffer(&stream, size)?;
+            }
+            stream.into()
+        }
+    };
+    recv_timeout(&stream, config.timeout)?;
+    send_timeout(&stream, config.timeout)?;
+    Connection::new(stream)
+}
+
+/// 连接建立后执行 AUTH 与 SELECT
+pub fn init(config: &Config, conn: &mut Connection) -> Result<(), RedisError> {
+    if let Some(auth) = &config.auth {
+        let rc = RedisCommand::build(&["AUTH", auth.as_str()])?;
+        execute(&rc, config, conn, true)?;
+    }
+
+    select_db(config, conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::redis_cli::{cli_send_command, reconnect, interrupt_on_ctrl_c, INTERRUPT_TARGET, command_hint, complete_command, connect, format_exec_reply, format_output, format_reply, has_open_quote, history_entry, monitor_args, pipe_mode, prompt, PubSubMessage, CommandType, OutputFormat, Protocol, CMD_TABLE, read_reply, repr_bytes, split_args, Config, Connection, RedisCommand, RedisValue, exit_code, EXIT_CONNECTION, EXIT_ERROR_REPLY, EXIT_OK, EXIT_PROTOCOL, EXIT_USAGE};
+    use crate::error::RedisError;
+    use std::io::{BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+
+    fn parse(input: &[u8]) -> Result<RedisValue, RedisError> {
+        let mut reader = input;
+        read_reply(&mut reader)
+    }
+
+    #[test]
+    fn test_read_reply_scalars() {
+        assert_eq!(parse(b"+OK\r\n").unwrap(), (RedisValue::Status("OK".to_string())));
+        assert_eq!(parse(b"-ERR unknown\r\n").unwrap(), (RedisValue::Error("ERR unknown".to_string())));
+        assert_eq!(parse(b":42\r\n").unwrap(), (RedisValue::Integer(42)));
+        assert_eq!(parse(b"$3\r\nbar\r\n").unwrap(), (RedisValue::Bulk(b"bar".to_vec())));
+        assert_eq!(parse(b"$-1\r\n").unwrap(), (RedisValue::Nil));
+        assert_eq!(parse(b"*-1\r\n").unwrap(), (RedisValue::Nil));
+        assert!(parse(b"?what\r\n").unwrap_err().is_protocol());
+        assert!(parse(b":abc\r\n").unwrap_err().is_protocol());
+        assert!(parse(b"+OK\n").unwrap_err().is_protocol());
+        assert!(parse(b"").unwrap_err().is_eof());
+        assert!(parse(b"$5\r\nab").unwrap_err().is_protocol());
+        assert!(parse(b"*2\r\n:1\r\n").unwrap_err().is_protocol());
+    }
+
+    #[test]
+    fn test_read_reply_nested_array() {
+        let reply = parse(b"*3\r\n:1\r\n*2\r\n$1\r\na\r\n$-1\r\n*0\r\n").unwrap();
+        assert_eq!(
+            reply,
+            RedisValue::Array(vec![
+                RedisValue::Integer(1),
+                RedisValue::Array(vec![RedisValue::Bulk(b"a".to_vec()), RedisValue::Nil]),
+                RedisValue::Array(vec![]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_format_reply() {
+        assert_eq!(format_reply(&RedisValue::Integer(3)), "(integer) 3\n");
+        assert_eq!(format_reply(&RedisValue::Error("ERR x".to_string())), "(error) ERR x\n");
+        assert_eq!(
+            format_reply(&RedisValue::Array(vec![RedisValue::Bulk(b"a".to_vec()), RedisValue::Nil])),
+            "1) \"a\"\n2) (nil)\n"
+        );
+        assert_eq!(format_reply(&RedisValue::Array(vec![])), "(empty list or set)\n");
+    }
+
+    #[test]
+    fn test_format_reply_nested() {
+        let mut exec = vec![RedisValue::Status("OK".to_string())];
+        exec.push(RedisValue::Array((0..10).map(|i| RedisValue::Bulk(i.to_string().into_bytes())).collect()));
+        exec.push(RedisValue::Array(vec![]));
+        exec.push(RedisValue::Array(vec![RedisValue::Array(vec![RedisValue::Integer(1)])]));
+        let expected = "\
+1) OK
+2)  1) \"0\"
+    2) \"1\"
+    3) \"2\"
+    4) \"3\"
+    5) \"4\"
+    6) \"5\"
+    7) \"6\"
+    8) \"7\"
+    9) \"8\"
+   10) \"9\"
+3) (empty list or set)
+4) 1) 1) (integer) 1
+";
+        assert_eq!(format_reply(&RedisValue::Array(exec)), expected);
+    }
+
+    #[test]
+    fn test_repr_bytes() {
+        assert_eq!(repr_bytes(b"hello"), "\"hello\"");
+        assert_eq!(repr_bytes(b"a\"b\\c"), "\"a\\\"b\\\\c\"");
+        assert_eq!(repr_bytes(b"\r\n\t"), "\"\\r\\n\\t\"");
+        assert_eq!(repr_bytes(&[0x00, 0x7f, 0xff, b'x']), "\"\\x00\\x7f\\xffx\"");
+    }
+
+    #[test]
+    fn test_binary_args_round_trip() {
+        let value = vec![0x08u8, 0x96, 0x01, 0x00, 0xff];
+        let cmd = RedisCommand::build(&[b"SET".to_vec(), b"blob".to_vec(), value.clone()]).unwrap();
+        let mut expected = b"*3\r\n$3\r\nSET\r\n$4\r\nblob\r\n$5\r\n".to_vec();
+        expected.extend_from_slice(&value);
+        expected.extend_from_slice(b"\r\n");
+        assert_eq!(cmd.to_resp(), expected);
+
+        let mut wire = b"$5\r\n".to_vec();
+        wire.extend_from_slice(&value);
+        wire.extend_from_slice(b"\r\n");
+        let mut reader = wire.as_slice();
+        assert_eq!(read_reply(&mut reader).unwrap(), RedisValue::Bulk(value));
+    }
+
+    #[test]
+    fn test_connection_keeps_pipelined_replies() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut sock, _) = listener.accept().unwrap();
+            // 三个回复一次性写出，客户端第一次读取就会把它们全部读进缓冲区
+            sock.write_all(b"+OK\r\n:2\r\n*2\r\n$1\r\na\r\n$1\r\nb\r\n").unwrap();
+        });
+
+        let mut conn = Connection::new(TcpStream::connect(addr).unwrap()).unwrap();
+        server.join().unwrap();
+        assert_eq!(conn.read_reply().unwrap(), RedisValue::Status("OK".to_string()));
+        assert_eq!(conn.read_reply().unwrap(), RedisValue::Integer(2));
+        assert_eq!(
+            conn.read_reply().unwrap(),
+            RedisValue::Array(vec![RedisValue::Bulk(b"a".to_vec()), RedisValue::Bulk(b"b".to_vec())])
+        );
+    }
+
+    fn args(list: &[&[u8]]) -> Vec<Vec<u8>> {
+        list.iter().map(|a| a.to_vec()).collect()
+    }
+
+    #[test]
+    fn test_build_errors_are_command_errors() {
+        assert!(RedisCommand::build(&["NOSUCH"]).unwrap_err().is_command());
+        assert!(RedisCommand::build(&["GET"]).unwrap_err().is_command());
+        assert!(RedisCommand::build(&["DEL", "a", "b"]).is_ok());
+        assert_eq!(RedisCommand::build::<&str>(&[]).unwrap_err().to_string(), "empty command");
+    }
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(split_args(b"  SET  foo bar \n").unwrap(), args(&[b"SET", b"foo", b"bar"]));
+        assert_eq!(split_args(b"SET greeting \"hello world\"").unwrap(), args(&[b"SET", b"greeting", b"hello world"]));
+        assert_eq!(split_args(b"SET k \"a\\r\\n\\t\\x00\\xff\\\"\"").unwrap(), args(&[b"SET", b"k", b"a\r\n\t\x00\xff\""]));
+        assert_eq!(split_args(b"SET k 'it\\'s \\n'").unwrap(), args(&[b"SET", b"k", b"it's \\n"]));
+        assert_eq!(split_args(b"SET k \"\"").unwrap(), args(&[b"SET", b"k", b""]));
+        assert_eq!(split_args(b"").unwrap(), Vec::<Vec<u8>>::new());
+        assert!(split_args(b"SET k \"unterminated").unwrap_err().is_command());
+        assert!(split_args(b"SET k 'unterminated").is_err());
+        assert!(split_args(b"SET k \"a\"b").is_err());
+    }
+
+    /// 只理解 multi-bulk 请求的简易服务端：ECHO 原样返回，BAD 返回错误，其余返回 +OK
+    fn spawn_echo_server() -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (sock, _) = listener.accept().unwrap();
+            let mut writer = sock.try_clone().unwrap();
+            let mut reader = BufReader::new(sock);
+            while let Ok(RedisValue::Array(args)) = read_reply(&mut reader) {
+                let reply = match &args[0] {
+                    RedisValue::Bulk(name) if name == b"ECHO" => match &args[1] {
+                        RedisValue::Bulk(b) => [format!("${}\r\n", b.len()).as_bytes(), b, b"\r\n"].concat(),
+                        _ => unreachable!(),
+                    },
+                    RedisValue::Bulk(name) if name == b"BAD" => b"-ERR bad\r\n".to_vec(),
+                    _ => b"+OK\r\n".to_vec(),
+                };
+                writer.write_all(&reply).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn test_pipe_mode_text_commands() {
+        let addr = spawn_echo_server();
+        let conn = Connection::new(TcpStream::connect(addr).unwrap()).unwrap();
+        let input: &[u8] = b"SET a 1\nSET b \"two words\"\n\nGET\nSET c 3\n";
+        let stats = pipe_mode(conn, input).unwrap();
+        assert_eq!((stats.replies, stats.errors), (3, 0));
+    }
+
+    #[test]
+    fn test_pipe_mode_raw_resp() {
+        let addr = spawn_echo_server();
+        let conn = Connection::new(TcpStream::connect(addr).unwrap()).unwrap();
+        let input: &[u8] = b"*1\r\n$4\r\nPING\r\n*1\r\n$3\r\nBAD\r\n*2\r\n$4\r\nECHO\r\n$2\r\nhi\r\n";
+        let stats = pipe_mode(conn, input).unwrap();
+        assert_eq!((stats.replies, stats.errors), (3, 1));
+    }
+
+    #[test]
+    fn test_format_output_machine_readable() {
+        let reply = RedisValue::Array(vec![
+            RedisValue::Bulk(b"say \"hi\"".to_vec()),
+            RedisValue::Integer(7),
+            RedisValue::Nil,
+            RedisValue::Array(vec![RedisValue::Status("OK".to_string())]),
+        ]);
+        assert_eq!(format_output(&reply, OutputFormat::Raw), b"say \"hi\"\n7\n\nOK\n".to_vec());
+        assert_eq!(format_output(&reply, OutputFormat::Csv), b"\"say \\\"hi\\\"\",7,NULL,\"OK\"\n".to_vec());
+        assert_eq!(format_output(&reply, OutputFormat::Json), b"[\"say \\\"hi\\\"\",7,null,[\"OK\"]]\n".to_vec());
+
+        // 值本身包含 "(integer)" 时 raw 输出不会被误认
+        let tricky = RedisValue::Bulk(b"(integer) 1".to_vec());
+        assert_eq!(format_output(&tricky, OutputFormat::Raw), b"(integer) 1\n".to_vec());
+        assert_eq!(format_output(&tricky, OutputFormat::Tty), b"\"(integer) 1\"\n".to_vec());
+
+        let err = RedisValue::Error("ERR no".to_string());
+        assert_eq!(format_output(&err, OutputFormat::Json), b"{\"error\":\"ERR no\"}\n".to_vec());
+        assert_eq!(format_output(&err, OutputFormat::Csv), b"ERROR,\"ERR no\"\n".to_vec());
+        assert_eq!(
+            format_output(&RedisValue::Bulk(vec![0xff, b'a', 0x01]), OutputFormat::Json),
+            b"\"\\u00ffa\\u0001\"\n".to_vec()
+        );
+    }
+
+    /// 按 1.x 服务端的方式解析一条请求：multi-bulk，或以空格切分的 inline 行（bulk 命令再读取
+    /// 最后一个参数的内容）。要求请求恰好占满 `wire`
+    fn parse_request(wire: &[u8]) -> Vec<Vec<u8>> {
+        let mut reader = wire;
+        let args = if wire[0] == b'*' {
+            match read_reply(&mut reader).unwrap() {
+                RedisValue::Array(items) => items
+                    .into_iter()
+                    .map(|item| match item {
+                        RedisValue::Bulk(b) => b,
+                        other => panic!("unexpected item {:?}", other),
+                    })
+                    .collect(),
+                other => panic!("unexpected request {:?}", other),
+            }
+        } else {
+            let end = reader.windows(2).position(|w| w == b"\r\n").unwrap();
+            let mut args: Vec<Vec<u8>> = reader[..end].split(|&b| b == b' ').map(|a| a.to_vec()).collect();
+            reader = &reader[end + 2..];
+            let cmd = CMD_TABLE.iter().find(|c| c.name.as_bytes().eq_ignore_ascii_case(&args[0])).unwrap();
+            if let CommandType::Bulk = cmd.flags {
+                let len: usize = String::from_utf8(args.pop().unwrap()).unwrap().parse().unwrap();
+                args.push(reader[..len].to_vec());
+                assert_eq!(&reader[len..len + 2], b"\r\n");
+                reader = &reader[len + 2..];
+            }
+            args
+        };
+        assert!(reader.is_empty(), "trailing bytes: {:?}", String::from_utf8_lossy(reader));
+        args
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let cases: &[&[&[u8]]] = &[
+            &[b"GET", b"plain"],
+            &[b"GET", b"with space"],
+            &[b"GET", b""],
+            &[b"GET", b"line\r\nbreak"],
+            &[b"DEL", b"a", b"b c", b"d\te"],
+            &[b"KEYS", b"*"],
+            &[b"SORT", b"list", b"BY", b"weight *"],
+            &[b"MGET", b"a", b"", b"\n"],
+            &[b"SET", b"k", b"value with spaces\r\n"],
+            &[b"SET", b"k v", b"1"],
+            &[b"SET", b"k", b""],
+            &[b"ZADD", b"z", b"1", b"member one"],
+            &[b"LSET", b"l", b"0", &[0x00, 0xff, b'\r']],
+            &[b"MSET", b"a b", b"1", b"c", b""],
+        ];
+
+        for case in cases {
+            let cmd = RedisCommand::build(case).unwrap();
+            let expected: Vec<Vec<u8>> = case.iter().map(|a| a.to_vec()).collect();
+            for protocol in [Protocol::MultiBulk, Protocol::Legacy] {
+                assert_eq!(parse_request(&cmd.encode(protocol)), expected, "{:?} {:?}", protocol, case);
+            }
+        }
+    }
+
+    #[test]
+    fn test_legacy_framing_upgrades_only_when_needed() {
+        let encode = |args: &[&str]| RedisCommand::build(args).unwrap().encode(Protocol::Legacy);
+        assert_eq!(encode(&["DEL", "a", "b"]), b"DEL a b\r\n".to_vec());
+        assert_eq!(encode(&["SET", "k", "a b"]), b"SET k 3\r\na b\r\n".to_vec());
+        assert_eq!(encode(&["DEL", "a b"]), b"*2\r\n$3\r\nDEL\r\n$3\r\na b\r\n".to_vec());
+        assert_eq!(encode(&["GET", ""]), b"*2\r\n$3\r\nGET\r\n$0\r\n\r\n".to_vec());
+        assert!(encode(&["SET", "k k", "v"]).starts_with(b"*3\r\n"));
+    }
+
+    /// 只服务一个连接：读到一条命令后写出 `reply`，然后关闭连接
+    fn spawn_scripted_server(reply: &'static [u8]) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (sock, _) = listener.accept().unwrap();
+            let mut writer = sock.try_clone().unwrap();
+            let mut reader = BufReader::new(sock);
+            if read_reply(&mut reader).is_ok() {
+                let _ = writer.write_all(reply);
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn test_monitor_fails_when_connection_drops() {
+        let addr = spawn_scripted_server(b"+OK\r\n+1339518083.2 [0 127.0.0.1:60866] \"GET\" \"k\"\r\n");
+        let mut config = Config::new();
+        config.hostport = addr.port();
+        let mut conn = connect(&config).unwrap();
+        assert_eq!(cli_send_command(&["MONITOR"], &mut config, &mut conn, true), EXIT_PROTOCOL);
+    }
+
+    #[test]
+    fn test_subscribe_fails_when_connection_drops() {
+        let addr = spawn_scripted_server(b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n");
+        let mut config = Config::new();
+        config.hostport = addr.port();
+        let mut conn = connect(&config).unwrap();
+        assert_eq!(cli_send_command(&["SUBSCRIBE", "news"], &mut config, &mut conn, true), EXIT_PROTOCOL);
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let io_err = || std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+        assert_eq!(exit_code(&RedisError::Server("ERR x".to_string())), EXIT_ERROR_REPLY);
+        assert_eq!(exit_code(&RedisError::Command("empty command".to_string())), EXIT_USAGE);
+        assert_eq!(exit_code(&RedisError::Resolve("nohost".to_string())), EXIT_CONNECTION);
+        assert_eq!(exit_code(&RedisError::Timeout(std::io::Error::from(std::io::ErrorKind::TimedOut))), EXIT_PROTOCOL);
+        assert_eq!(exit_code(&RedisError::Io(io_err())), EXIT_PROTOCOL);
+        assert_eq!(exit_code(&RedisError::Protocol("bad".to_string())), EXIT_PROTOCOL);
+
+        let addr = spawn_scripted_server(b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n");
+        let mut config = Config::new();
+        config.hostport = addr.port();
+        let mut conn = connect(&config).unwrap();
+        assert_eq!(cli_send_command(&["NOSUCHCMD"], &mut config, &mut conn, true), EXIT_USAGE);
+        assert_eq!(cli_send_command(&["INCR", "k"], &mut config, &mut conn, true), EXIT_ERROR_REPLY);
+    }
+
+    #[test]
+    fn test_repeat_keeps_first_error_code() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (sock, _) = listener.accept().unwrap();
+            let mut writer = sock.try_clone().unwrap();
+            let mut reader = BufReader::new(sock);
+            // 第一次回复错误，之后都成功
+            let mut reply: &[u8] = b"-ERR value is not an integer\r\n";
+            while read_reply(&mut reader).is_ok() {
+                let _ = writer.write_all(reply);
+                reply = b":1\r\n";
+            }
+        });
+
+        let mut config = Config::new();
+        config.hostport = port;
+        config.repeat = 3;
+        let mut conn = connect(&config).unwrap();
+        assert_eq!(cli_send_command(&["INCR", "k"], &mut config, &mut conn, true), EXIT_ERROR_REPLY);
+    }
+
+    #[test]
+    fn test_streaming_state() {
+        let addr = spawn_echo_server();
+        let mut config = Config::new();
+        config.hostport = addr.port();
+        let mut conn = connect(&config).unwrap();
+        // 本地校验失败或服务端报错时没有进入推送状态，REPL 应继续
+        assert_ne!(cli_send_command(&["SUBSCRIBE"], &mut config, &mut conn, true), EXIT_OK);
+        assert_ne!(cli_send_command(&["MONITOR", "x"], &mut config, &mut conn, true), EXIT_OK);
+        assert!(!conn.is_streaming());
+
+        let addr = spawn_scripted_server(b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n");
+        config.hostport = addr.port();
+        let mut conn = connect(&config).unwrap();
+        cli_send_command(&["SUBSCRIBE", "news"], &mut config, &mut conn, true);
+        assert!(conn.is_streaming());
+    }
+
+    #[test]
+    fn test_monitor_args() {
+        let v2 = RedisValue::Status(r#"1339518083.107412 [0 127.0.0.1:60866] "SET" "user:1" "a b""#.to_string());
+        assert_eq!(monitor_args(&v2).unwrap(), args(&[b"SET", b"user:1", b"a b"]));
+        let v1 = RedisValue::Array(vec![RedisValue::Bulk(b"DEL".to_vec()), RedisValue::Bulk(b"k".to_vec())]);
+        assert_eq!(monitor_args(&v1).unwrap(), args(&[b"DEL", b"k"]));
+        assert_eq!(monitor_args(&RedisValue::Integer(1)), None);
+    }
+
+    #[test]
+    fn test_pubsub_message_from_reply() {
+        let frame = |items: &[&[u8]]| RedisValue::Array(items.iter().map(|b| RedisValue::Bulk(b.to_vec())).collect());
+        assert_eq!(
+            PubSubMessage::from_reply(&frame(&[b"pmessage", b"news.*", b"news.it", b"hi"])),
+            Some(PubSubMessage::PMessage { pattern: b"news.*".to_vec(), channel: b"news.it".to_vec(), payload: b"hi".to_vec() })
+        );
+        let unsubscribed = RedisValue::Array(vec![RedisValue::Bulk(b"unsubscribe".to_vec()), RedisValue::Nil, RedisValue::Integer(0)]);
+        assert_eq!(
+            PubSubMessage::from_reply(&unsubscribed),
+            Some(PubSubMessage::Subscription { kind: "unsubscribe".to_string(), channel: None, count: 0 })
+        );
+        assert_eq!(PubSubMessage::from_reply(&frame(&[b"message", b"only-channel"])), None);
+        assert_eq!(PubSubMessage::from_reply(&RedisValue::Status("OK".to_string())), None);
+    }
+
+    #[test]
+    fn test_transaction_tracking() {
+        let addr = spawn_echo_server();
+        let mut conn = Connection::new(TcpStream::connect(addr).unwrap()).unwrap();
+        let ok = RedisValue::Status("OK".to_string());
+        let queued = RedisValue::Status("QUEUED".to_string());
+
+        assert!(conn.track_transaction(&RedisCommand::build(&["SET", "a", "b"]).unwrap(), &queued).is_none());
+        assert!(conn.queued().is_none());
+
+        conn.track_transaction(&RedisCommand::build(&["MULTI"]).unwrap(), &ok);
+        conn.track_transaction(&RedisCommand::build(&["SET", "k", "hello world"]).unwrap(), &queued);
+        conn.track_transaction(&RedisCommand::build(&["INCR", "n"]).unwrap(), &queued);
+        let displays: Vec<_> = conn.queued().unwrap().iter().map(|cmd| cmd.display.as_str()).collect();
+        assert_eq!(displays, ["SET k \"hello world\"", "INCR n"]);
+
+        let reply = RedisValue::Array(vec![ok.clone(), RedisValue::Integer(3)]);
+        let cmds = conn.track_transaction(&RedisCommand::build(&["EXEC"]).unwrap(), &reply).unwrap();
+        assert!(conn.queued().is_none());
+        assert_eq!(format_exec_reply(&cmds, &reply), "1) SET k \"hello world\"\n   OK\n2) INCR n\n   (integer) 3\n");
+
+        conn.track_transaction(&RedisCommand::build(&["MULTI"]).unwrap(), &ok);
+        conn.track_transaction(&RedisCommand::build(&["DISCARD"]).unwrap(), &ok);
+        assert!(conn.queued().is_none());
+    }
+
+    #[test]
+    fn test_blocking_commands_extend_read_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (sock, _) = listener.accept().unwrap();
+            let mut writer = sock.try_clone().unwrap();
+            let mut reader = BufReader::new(sock);
+            // 每条回复都延迟 300ms
+            while read_reply(&mut reader).is_ok() {
+                thread::sleep(Duration::from_millis(300));
+                let _ = writer.write_all(b"$-1\r\n");
+            }
+        });
+
+        let blpop = RedisCommand::build(&["BLPOP", "queue", "1"]).unwrap();
+        assert_eq!(blpop.blocking_secs(), Some(1));
+        assert_eq!(RedisCommand::build(&["GET", "queue"]).unwrap().blocking_secs(), None);
+
+        let mut conn = Connection::new(TcpStream::connect(addr).unwrap()).unwrap();
+        conn.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+        assert_eq!(conn.request(&blpop, Protocol::MultiBulk).unwrap(), RedisValue::Nil);
+        let get = RedisCommand::build(&["GET", "queue"]).unwrap();
+        assert!(conn.request(&get, Protocol::MultiBulk).unwrap_err().is_timeout());
+    }
+
+    #[test]
+    fn test_late_reply_is_not_read_by_next_command() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            // 前两个连接的回复迟到 300ms，第三个连接立即回复
+            for (delay, reply) in [(300, b"$6\r\nreply1\r\n"), (300, b"$6\r\nreply1\r\n"), (0, b"$6\r\nreply2\r\n")] {
+                let (sock, _) = listener.accept().unwrap();
+                let mut writer = sock.try_clone().unwrap();
+                let mut reader = BufReader::new(sock);
+                thread::spawn(move || {
+                    while read_reply(&mut reader).is_ok() {
+                        thread::sleep(Duration::from_millis(delay));
+                        let _ = writer.write_all(reply);
+                    }
+                });
+            }
+        });
+
+        let get_a = RedisCommand::build(&["GET", "a"]).unwrap();
+        let get_b = RedisCommand::build(&["GET", "b"]).unwrap();
+        let mut config = Config::new();
+        config.hostport = port;
+        config.timeout = Some(Duration::from_millis(100));
+
+        // 超时后连接被关闭，迟到的回复不会被当作下一条命令的回复
+        let mut conn = connect(&config).unwrap();
+        assert!(conn.request(&get_a, Protocol::MultiBulk).unwrap_err().is_timeout());
+        thread::sleep(Duration::from_millis(400));
+        assert!(conn.request(&get_b, Protocol::MultiBulk).unwrap_err().is_io());
+
+        // 命令行在下一条命令前重连，读到的是自己的回复
+        let mut conn = connect(&config).unwrap();
+        assert_eq!(cli_send_command(&["GET", "a"], &mut config, &mut conn, true), EXIT_PROTOCOL);
+        assert!(conn.is_broken());
+        thread::sleep(Duration::from_millis(400));
+        assert_eq!(cli_send_command(&["GET", "b"], &mut config, &mut conn, true), EXIT_OK);
+        assert!(!conn.is_broken());
+        assert_eq!(conn.request(&get_b, Protocol::MultiBulk).unwrap(), RedisValue::Bulk(b"reply2".to_vec()));
+    }
+
+    #[test]
+    fn test_reconnect_does_not_retry_timeouts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            // 接受连接但从不回复
+            let mut socks = Vec::new();
+            for sock in listener.incoming() {
+                socks.push(sock);
+            }
+        });
+
+        let mut config = Config::new();
+        config.hostport = port;
+        config.auth = Some("secret".to_string());
+        config.timeout = Some(Duration::from_millis(100));
+        let mut conn = connect(&config).unwrap();
+        let start = std::time::Instant::now();
+        assert!(reconnect(&config, &mut conn).unwrap_err().is_timeout());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_reconnect_retries_only_idempotent_commands() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let mut received = Vec::new();
+            // 每个连接只读一条命令：第奇数个连接不回复直接关闭，模拟服务端重启
+            for i in 0..3 {
+                let (sock, _) = listener.accept().unwrap();
+                let mut writer = sock.try_clone().unwrap();
+                let mut reader = BufReader::new(sock);
+                if let Ok(RedisValue::Array(args)) = read_reply(&mut reader) {
+                    received.push(args);
+                }
+                if i % 2 == 1 {
+                    writer.write_all(b"+OK\r\n").unwrap();
+                    let _ = read_reply(&mut reader);
+                }
+            }
+            received
+        });
+
+        let mut config = Config::new();
+        config.hostport = port;
+        let mut conn = connect(&config).unwrap();
+        assert_eq!(cli_send_command(&["SET", "k", "v"], &mut config, &mut conn, true), EXIT_OK);
+        drop(conn);
+
+        let mut conn = connect(&config).unwrap();
+        assert_eq!(cli_send_command(&["INCR", "n"], &mut config, &mut conn, true), EXIT_PROTOCOL);
+        // 不重试的命令不立即重连，连接留待下一条命令前重建
+        assert!(conn.is_broken());
+        drop(conn);
+
+        let names: Vec<_> = server.join().unwrap().iter().map(|args| args[0].clone()).collect();
+        let set = RedisValue::Bulk(b"SET".to_vec());
+        let incr = RedisValue::Bulk(b"INCR".to_vec());
+        assert_eq!(names, [set.clone(), set, incr]);
+
+        // 破坏性的命令即使幂等也不自动重试
+        assert!(!RedisCommand::build(&["FLUSHDB"]).unwrap().is_idempotent());
+        assert!(!RedisCommand::build(&["FLUSHALL"]).unwrap().is_idempotent());
+    }
+
+    #[test]
+    fn test_repl_line_helpers() {
+        assert!(!has_open_quote(b"SET k v"));
+        assert!(has_open_quote(b"SET k \"line one"));
+        assert!(!has_open_quote(b"SET k \"line one\nline two\""));
+        assert!(has_open_quote(b"SET k 'it\\'s"));
+        assert!(!has_open_quote(b"SET k \"say \\\"hi\\\"\""));
+        // 单引号内只有 \' 是转义，\\ 不是：这里最后的 \' 转义了引号，引号仍未闭合
+        assert!(has_open_quote(b"SET k 'a\\\\'"));
+        assert!(split_args(b"SET k 'a\\\\'").is_err());
+        assert!(!has_open_quote(b"SET k 'a\\b'"));
+        assert_eq!(split_args(b"SET k 'a\\b'").unwrap()[2], b"a\\b");
+        assert_eq!(split_args(b"SET k \"a\nb\"").unwrap()[2], b"a\nb");
+
+        assert_eq!(history_entry(b"auth s3cret"), "auth <redacted>");
+        assert_eq!(history_entry(b"  AUTH \"pass word\""), "AUTH <redacted>");
+        assert_eq!(history_entry(b"GET auth"), "GET auth");
+        // 引号未闭合、无法解析时同样替换
+        assert_eq!(history_entry(b"AUTH \"my secret"), "AUTH <redacted>");
+        assert_eq!(history_entry(b"\"auth\" 'pass"), "\"auth\" <redacted>");
+    }
+
+    #[test]
+    fn test_completion_and_hints() {
+        let find = |name: &str| CMD_TABLE.iter().find(|c| c.name == name).unwrap();
+        assert_eq!(find("ZADD").syntax(), "ZADD key score member");
+        assert_eq!(find("BLPOP").syntax(), "BLPOP key [key ...] timeout");
+        assert_eq!(find("MSET").syntax(), "MSET key value [key value ...]");
+        assert_eq!(find("UNSUBSCRIBE").syntax(), "UNSUBSCRIBE [channel ...]");
+        assert_eq!(find("SORT").syntax(), "SORT key [option ...]");
+
+        let (start, candidates) = complete_command("zrev");
+        assert_eq!(start, 0);
+        assert_eq!(candidates, ["zrevrank", "zrevrange"]);
+        let (start, candidates) = complete_command("  HGETA");
+        assert_eq!(start, 2);
+        assert_eq!(candidates, ["HGETALL"]);
+        assert!(complete_command("GET k").1.is_empty());
+        let (start, candidates) = complete_command("help @so");
+        assert_eq!(start, 5);
+        assert_eq!(candidates, ["@sorted_set"]);
+
+        assert_eq!(command_hint("zadd").as_deref(), Some(" key score member"));
+        assert_eq!(command_hint("ZADD myset ").as_deref(), Some("score member"));
+        assert_eq!(command_hint("ZADD myset 1 a "), None);
+        assert_eq!(command_hint("ZADD myse"), None);
+        assert_eq!(command_hint("BLPOP a b c ").as_deref(), Some("[key ...] timeout"));
+        assert_eq!(command_hint("NOPE "), None);
+    }
+
+    #[test]
+    fn test_prompt_tracks_selected_db() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (sock, _) = listener.accept().unwrap();
+            let mut writer = sock.try_clone().unwrap();
+            let mut reader = BufReader::new(sock);
+            // 按顺序回复：SELECT 3、MULTI、SELECT 5、SET、EXEC、MULTI、SELECT 7、EXEC（SELECT 7 失败）
+            let replies: [&[u8]; 8] = [
+                b"+OK\r\n",
+                b"+OK\r\n",
+                b"+QUEUED\r\n",
+                b"+QUEUED\r\n",
+                b"*2\r\n+OK\r\n+OK\r\n",
+                b"+OK\r\n",
+                b"+QUEUED\r\n",
+                b"*1\r\n-ERR DB index is out of range\r\n",
+            ];
+            for reply in replies {
+                if read_reply(&mut reader).is_err() {
+                    break;
+                }
+                writer.write_all(reply).unwrap();
+            }
+        });
+
+        let mut config = Config::new();
+        config.hostport = addr.port();
+        let mut conn = connect(&config).unwrap();
+        assert_eq!(prompt(&config, conn.queued()), format!("127.0.0.1:{}> ", addr.port()));
+
+        assert_eq!(cli_send_command(&["SELECT", "3"], &mut config, &mut conn, true), EXIT_OK);
+        assert_eq!(config.dbnum, 3);
+        assert_eq!(prompt(&config, conn.queued()), format!("127.0.0.1:{}[3]> ", addr.port()));
+
+        // 事务中的 SELECT 在 EXEC 之前不生效
+        cli_send_command(&["MULTI"], &mut config, &mut conn, true);
+        cli_send_command(&["SELECT", "5"], &mut config, &mut conn, true);
+        cli_send_command(&["SET", "k", "v"], &mut config, &mut conn, true);
+        assert_eq!(config.dbnum, 3);
+        assert_eq!(prompt(&config, conn.queued()), format!("127.0.0.1:{}[3](TX 2)> ", addr.port()));
+
+        // EXEC 中回复 +OK 的 SELECT 生效
+        assert_eq!(cli_send_command(&["EXEC"], &mut config, &mut conn, true), EXIT_OK);
+        assert_eq!(config.dbnum, 5);
+        assert_eq!(prompt(&config, conn.queued()), format!("127.0.0.1:{}[5]> ", addr.port()));
+
+        // 执行失败的 SELECT 不改变当前数据库
+        cli_send_command(&["MULTI"], &mut config, &mut conn, true);
+        cli_send_command(&["SELECT", "7"], &mut config, &mut conn, true);
+        cli_send_command(&["EXEC"], &mut config, &mut conn, true);
+        assert_eq!(config.dbnum, 5);
+    }
+
+    #[test]
+    fn test_apply_uri() {
+        let mut config = Config::new();
+        config.apply_uri("redis://:p%40ss@10.0.0.2:6380/3").unwrap();
+        assert_eq!((config.hostip.as_str(), config.hostport, config.dbnum), ("10.0.0.2", 6380, 3));
+        assert_eq!(config.auth.as_deref(), Some("p@ss"));
+
+        let mut config = Config::new();
+        config.apply_uri("redis://[::1]").unwrap();
+        assert_eq!((config.hostip.as_str(), config.hostport, config.dbnum), ("::1", 6379, 0));
+        assert_eq!(config.auth, None);
+
+        assert!(Config::new().apply_uri("http://localhost").unwrap_err().is_command());
+        assert!(Config::new().apply_uri("redis://127.0.0.1:port").unwrap_err().is_command());
+        assert!(Config::new().apply_uri("redis://127.0.0.1/999").unwrap_err().is_command());
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let args = |list: &[&str]| list.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let mut config = Config::new();
+        assert_eq!(config.parse_options(&args(&["r-cli", "-p", "6380", "-n", "2", "GET", "k"])).unwrap(), 5);
+        assert_eq!((config.hostport, config.dbnum), (6380, 2));
+
+        // 选项错误以错误返回，由调用方决定如何退出
+        assert!(Config::new().parse_options(&args(&["r-cli", "-p", "port"])).unwrap_err().is_command());
+        assert!(Config::new().parse_options(&args(&["r-cli", "-h"])).unwrap_err().is_command());
+        assert!(Config::new().parse_options(&args(&["r-cli", "-u", "http://x"])).unwrap_err().is_command());
+    }
+
+    #[test]
+    fn test_unix_socket_connection() {
+        use std::os::unix::net::UnixListener;
+
+        let path = std::env::temp_dir().join(format!("r-cli-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        thread::spawn(move || {
+            let (sock, _) = listener.accept().unwrap();
+            let mut writer = sock.try_clone().unwrap();
+            let mut reader = BufReader::new(sock);
+            while read_reply(&mut reader).is_ok() {
+                writer.write_all(b"+PONG\r\n").unwrap();
+            }
+        });
+
+        let mut config = Config::new();
+        config.hostsocket = Some(path.to_string_lossy().into_owned());
+        assert_eq!(config.server_addr(), path.to_string_lossy());
+        let mut conn = connect(&config).unwrap();
+        let ping = RedisCommand::build(&["PING"]).unwrap();
+        assert_eq!(conn.request(&ping, Protocol::MultiBulk).unwrap(), RedisValue::Status("PONG".to_string()));
+        let _ = std::fs::remove_file(&path);
     }
 
-    if let Err(err) = select_db(config.dbnum, stream) {
-        eprintln!("{}", err);
+    #[test]
+    fn test_interrupt_target_is_cleared_on_drop() {
+        let addr = spawn_echo_server();
+        let conn = Connection::new(TcpStream::connect(addr).unwrap()).unwrap();
+        let guard = interrupt_on_ctrl_c(&conn).unwrap();
+        assert!(INTERRUPT_TARGET.lock().unwrap().is_some());
+        drop(guard);
+        assert!(INTERRUPT_TARGET.lock().unwrap().is_none());
     }
 }
@@ -0,0 +1,106 @@
+/// 仿照 util.c 的 stringmatchlen：glob 风格匹配，支持 `*`、`?`、`[abc]`、`[^a-z]` 和 `\` 转义
+pub fn string_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let eq = |a: u8, b: u8| if nocase { a.eq_ignore_ascii_case(&b) } else { a == b };
+    let (mut p, mut s) = (0, 0);
+
+    while p < pattern.len() {
+        match pattern[p] {
+            b'*' => {
+                while p + 1 < pattern.len() && pattern[p + 1] == b'*' {
+                    p += 1;
+                }
+                if p + 1 == pattern.len() {
+                    return true;
+                }
+                return (s..=string.len()).any(|start| string_match(&pattern[p + 1..], &string[start..], nocase));
+            }
+            b'?' => {
+                if s >= string.len() {
+                    return false;
+                }
+                s += 1;
+            }
+            b'[' => {
+                if s >= string.len() {
+                    return false;
+                }
+                p += 1;
+                let not = pattern.get(p) == Some(&b'^');
+                if not {
+                    p += 1;
+                }
+                let mut matched = false;
+                loop {
+                    match pattern.get(p) {
+                        // 未闭合的 `[`，与 C 实现一样视为到模式末尾结束
+                        None => {
+                            p -= 1;
+                            break;
+                        }
+                        Some(b']') => break,
+                        Some(b'\\') if p + 1 < pattern.len() => {
+                            p += 1;
+                            matched |= eq(pattern[p], string[s]);
+                        }
+                        Some(&start) if pattern.get(p + 1) == Some(&b'-') && p + 2 < pattern.len() => {
+                            let end = pattern[p + 2];
+                            let (lo, hi) = if start <= end { (start, end) } else { (end, start) };
+                            let c = string[s];
+                            matched |= if nocase {
+                                (lo.to_ascii_lowercase()..=hi.to_ascii_lowercase()).contains(&c.to_ascii_lowercase())
+                            } else {
+                                (lo..=hi).contains(&c)
+                            };
+                            p += 2;
+                        }
+                        Some(&c) => matched |= eq(c, string[s]),
+                    }
+                    p += 1;
+                }
+                if matched == not {
+                    return false;
+                }
+                s += 1;
+            }
+            b'\\' if p + 1 < pattern.len() => {
+                p += 1;
+                if s >= string.len() || !eq(pattern[p], string[s]) {
+                    return false;
+                }
+                s += 1;
+            }
+            c => {
+                if s >= string.len() || !eq(c, string[s]) {
+                    return false;
+                }
+                s += 1;
+            }
+        }
+        p += 1;
+    }
+    s == string.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::string_match;
+
+    #[test]
+    fn test_string_match() {
+        assert!(string_match(b"*", b"anything", false));
+        assert!(string_match(b"user:*", b"user:42", false));
+        assert!(!string_match(b"user:*", b"session:42", false));
+        assert!(string_match(b"h?llo", b"hello", false));
+        assert!(!string_match(b"h?llo", b"hllo", false));
+        assert!(string_match(b"h[ae]llo", b"hallo", false));
+        assert!(!string_match(b"h[^e]llo", b"hello", false));
+        assert!(string_match(b"h[a-c]llo", b"hbllo", false));
+        assert!(string_match(b"*:[0-9]*:end", b"a:1234:end", false));
+        assert!(string_match(b"a\\*b", b"a*b", false));
+        assert!(!string_match(b"a\\*b", b"axb", false));
+        assert!(string_match(b"USER:*", b"user:1", true));
+        assert!(!string_match(b"USER:*", b"user:1", false));
+        assert!(string_match(b"", b"", false));
+        assert!(!string_match(b"", b"x", false));
+    }
+}
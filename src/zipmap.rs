@@ -129,7 +129,7 @@ impl ZipMap {
 
             i = vend;
         }
-        s.push_str("}");
+        s.push('}');
         s
     }
 
@@ -312,14 +312,14 @@ mod tests {
             zm_insert_times.push(start.elapsed());
 
             let start = Instant::now();
-            for i in 0..n {
-                zm.get(&keys[i]);
+            for key in keys.iter().take(n) {
+                zm.get(key);
             }
             zm_get_times.push(start.elapsed());
 
             let start = Instant::now();
-            for i in 0..n {
-                zm.del(&keys[i]);
+            for key in keys.iter().take(n) {
+                zm.del(key);
             }
             zm_del_times.push(start.elapsed());
 
@@ -332,14 +332,14 @@ mod tests {
             hm_insert_times.push(start.elapsed());
 
             let start = Instant::now();
-            for i in 0..n {
-                hm.get(&keys[i]);
+            for key in keys.iter().take(n) {
+                hm.get(key);
             }
             hm_get_times.push(start.elapsed());
 
             let start = Instant::now();
-            for i in 0..n {
-                hm.remove(&keys[i]);
+            for key in keys.iter().take(n) {
+                hm.remove(key);
             }
             hm_del_times.push(start.elapsed());
         }
@@ -13,7 +13,7 @@ run_test() {
     local cmd="$1"
     ((TOTAL++))
 
-    output=$($RCLI -h $HOST -p $PORT -a $AUTH $cmd)
+    output=$($RCLI -h $HOST -p $PORT -a $AUTH --no-raw $cmd)
 
     echo "== 测试命令: $cmd =="
     echo "输出: $output"
@@ -114,6 +114,7 @@ COMMANDS=(
     "HVALS myhash"
     "HGETALL myhash"
     "HEXISTS myhash field1"
+    "PUBLISH news hello"
     "RENAME foo1 bar"
     "RENAMENX foo2 bar"
 #    "DEBUG OBJECT foo"